@@ -0,0 +1,382 @@
+// Puzzle-independent entry point: JSON in, JSON out
+
+use crate::error::{Error, ErrorCode};
+use crate::branching::{Branching, ValueOrdering};
+use crate::puzzles::*;
+use crate::solver::{Config, Observer};
+
+use serde::Deserialize;
+use serde::Serialize;
+use schemars::JsonSchema;
+use serde_json::Value;
+use std::hash::{Hash, Hasher};
+
+// Version of the request and response formats. Requests can give the version they were written for, so that
+// the formats can change without old clients being misread: requests without one are taken to be from
+// version 1, and those from a newer version than this one are rejected.
+pub const API_VERSION: u64 = 1;
+
+#[derive(Deserialize, JsonSchema, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Input {
+    Sudoku(sudoku::api::Input)
+}
+
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Output {
+    Sudoku(sudoku::api::Output)
+}
+
+// A link to open a puzzle in an online player, along with the data it encodes
+#[derive(Serialize, Debug)]
+pub struct Share {
+    pub url: String,
+    pub data: serde_json::Value,
+}
+
+// The outcome of one puzzle in a batch, so that one bad puzzle doesn't fail the rest
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchResult {
+    Output(Output),
+    Error(Error),
+}
+
+impl From<Result<Output, Error>> for BatchResult {
+
+    fn from(result: Result<Output, Error>) -> Self {
+        match result {
+            Ok(output) => BatchResult::Output(output),
+            Err(error) => BatchResult::Error(error),
+        }
+    }
+
+}
+
+// What the solver supports, so that clients can hide what it doesn't
+#[derive(Serialize, Debug)]
+pub struct Capabilities {
+    // By puzzle type, as given in `type`
+    pub puzzles: serde_json::Map<String, serde_json::Value>,
+    pub config: ConfigCapabilities,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ConfigCapabilities {
+    // Options every request has to give
+    pub required: Vec<&'static str>,
+    // Every option, with the value used when it's left out (or a typical value, if it's required)
+    pub defaults: Config,
+    pub branching: Vec<Branching>,
+    pub value_ordering: Vec<ValueOrdering>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Version {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub api_version: u64,
+    // Latest version of the puzzle file format that can be read
+    pub puzzle_file_version: u64,
+}
+
+// The puzzle in its canonical form, so that equivalent inputs share results, and its hash to look it up by.
+// Different puzzles can share a hash, so lookups must compare the canonical form too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey {
+    pub hash: u64,
+    pub canonical: String,
+}
+
+impl Input {
+
+    pub fn cache_key(&self) -> CacheKey {
+        let canonical = match self {
+            Input::Sudoku(input) => format!("sudoku {}", input.canonical()),
+        };
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        canonical.hash(&mut hasher);
+        return CacheKey { hash: hasher.finish(), canonical };
+    }
+
+    // Apply limits on top of those requested
+    pub fn limit(&mut self, timeout_ms: Option<u64>) {
+        match self {
+            Input::Sudoku(input) => {
+                input.config.timeout_ms = match (input.config.timeout_ms, timeout_ms) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+            }
+        }
+    }
+
+}
+
+pub fn solve(input: Input) -> Result<Output, Error> {
+    match input {
+        Input::Sudoku(input_data) => match sudoku::solve(input_data) {
+            Ok(output_data) => Ok(Output::Sudoku(output_data)),
+            Err(e)          => Err(e),
+        }
+    }
+}
+
+// Solve, telling the observer about progress and breadcrumbs as they happen, and checking whether to stop
+pub fn solve_observed(input: Input, observer: Box<dyn Observer>) -> Result<Output, Error> {
+    match input {
+        Input::Sudoku(input_data) => sudoku::solve_observed(input_data, Some(observer)).map(Output::Sudoku),
+    }
+}
+
+pub fn capabilities() -> Capabilities {
+    // One for each variant of Input. Capabilities only hold strings and the like, which always serialize
+    let mut puzzles = serde_json::Map::new();
+    puzzles.insert("sudoku".to_string(), serde_json::to_value(sudoku::api::capabilities()).unwrap());
+    return Capabilities {
+        puzzles,
+        config: ConfigCapabilities {
+            required: vec!["greedy", "breadcrumbs"],
+            defaults: Config::default(),
+            branching: Branching::ALL.to_vec(),
+            value_ordering: ValueOrdering::ALL.to_vec(),
+        },
+    };
+}
+
+pub fn version() -> Version {
+    return Version {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        api_version: API_VERSION,
+        puzzle_file_version: sudoku::file::VERSION,
+    };
+}
+
+// JSON Schema for requests (each variant of Input, along with `api_version`)
+pub fn input_schema() -> Value {
+    let mut schema = schemars::schema_for!(Input).to_value();
+    let api_version = serde_json::json!({ "type": "integer", "minimum": 1, "maximum": API_VERSION, "default": 1 });
+    for variant in schema["oneOf"].as_array_mut().unwrap() {
+        variant["properties"].as_object_mut().unwrap().insert("api_version".to_string(), api_version.clone());
+    }
+    return schema;
+}
+
+// JSON Schema for the results of solving
+pub fn output_schema() -> Value {
+    return schemars::schema_for!(Output).to_value();
+}
+
+pub fn share(input: &Input) -> Result<Share, Error> {
+    match input {
+        Input::Sudoku(input_data) => {
+            let data = sudoku::fpuzzles::export(input_data)?;
+            let url = format!("{}{}", sudoku::fpuzzles::URL_PREFIX, sudoku::fpuzzles::compress(&data));
+            Ok(Share { url, data })
+        }
+    }
+}
+
+pub fn parse(input_bytes: &[u8]) -> Result<Input, Error> {
+    let value : serde_json::Value = serde_json::from_slice(input_bytes)
+        .map_err(|err| Error::new(ErrorCode::MalformedInput, err.to_string()))?;
+    return parse_input(value);
+}
+
+// Parse a JSON array of inputs, each on its own, failing only if the batch itself is malformed
+pub fn parse_batch(input_bytes: &[u8]) -> Result<Vec<Result<Input, Error>>, Error> {
+    let value : serde_json::Value = serde_json::from_slice(input_bytes)
+        .map_err(|err| Error::new(ErrorCode::MalformedInput, err.to_string()))?;
+    match value {
+        serde_json::Value::Array(values) => Ok(values.into_iter().map(parse_input).collect()),
+        _ => Err(Error::new(ErrorCode::MalformedInput, "expected an array of puzzles".to_string())),
+    }
+}
+
+fn check_api_version(value: &Value) -> Result<(), Error> {
+    match value.get("api_version").map(Value::as_u64) {
+        None => Ok(()),
+        Some(Some(version)) if (1..=API_VERSION).contains(&version) => Ok(()),
+        Some(Some(version)) => {
+            let message = format!("api version {} isn't supported (latest is {})", version, API_VERSION);
+            Err(Error::new(ErrorCode::Unsupported, message).with_field("api_version".to_string()))
+        },
+        Some(None) => Err(Error::new(ErrorCode::MalformedInput, "api_version must be a number".to_string()).with_field("api_version".to_string())),
+    }
+}
+
+// Parse an input for the Python and JavaScript bindings, which can leave out the type of a sudoku
+pub fn parse_in_process(mut value: serde_json::Value) -> Result<Input, Error> {
+    if let Some(fields) = value.as_object_mut() {
+        fields.entry("type").or_insert(Value::from("sudoku"));
+    }
+    return parse_input(value);
+}
+
+pub fn parse_input(value: serde_json::Value) -> Result<Input, Error> {
+    check_api_version(&value)?;
+    // serde loses track of the path inside internally tagged enums, so dispatch on the type ourselves
+    match value.get("type").map(|t| t.as_str()) {
+        Some(Some("sudoku")) => Ok(Input::Sudoku(parse_value(value)?)),
+        Some(Some(other))    => Err(Error::new(ErrorCode::Unsupported, format!("unknown puzzle type `{}`", other)).with_field("type".to_string())),
+        Some(None)           => Err(Error::new(ErrorCode::MalformedInput, "puzzle type must be a string".to_string()).with_field("type".to_string())),
+        None                 => Err(Error::new(ErrorCode::MalformedInput, "missing field `type`".to_string())),
+    }
+}
+
+pub(crate) fn parse_value<T: serde::de::DeserializeOwned>(value: serde_json::Value) -> Result<T, Error> {
+    serde_path_to_error::deserialize(value).map_err(|err| {
+        let path = err.path().to_string();
+        let err = err.into_inner();
+        // Unknown constraint types are well formed, but not something we can solve. Other unknown variants, like
+        // a misspelt option, are mistakes in the input
+        let code = if path.ends_with("].type") && err.to_string().starts_with("unknown variant") {
+            ErrorCode::Unsupported
+        } else {
+            ErrorCode::MalformedInput
+        };
+        let error = Error::new(code, err.to_string());
+        if path == "." { error } else { error.with_field(path) }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    fn sudoku(domains: serde_json::Value) -> serde_json::Value {
+        json!({
+            "type": "sudoku",
+            "domains": domains,
+            "constraints": { "globals": { "anti_knight": false, "anti_king": false }, "locals": [] },
+            "config": { "greedy": false, "breadcrumbs": false },
+        })
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        let line = "48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....";
+        let domains = sudoku::text::parse(line).unwrap();
+        let batch = json!([sudoku(json!(domains)), { "type": "kakuro" }, sudoku(json!({ "1:1": [1] }))]);
+        let inputs = parse_batch(batch.to_string().as_bytes()).unwrap();
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(inputs[1].as_ref().unwrap_err().code, ErrorCode::Unsupported);
+
+        let results = inputs.into_iter().map(|input| BatchResult::from(input.and_then(solve))).collect::<Vec<_>>();
+        let results = serde_json::to_value(results).unwrap();
+        assert_eq!(results[0]["output"]["result"], "solved");
+        assert_eq!(results[1]["error"]["code"], "unsupported");
+        assert_eq!(results[2]["error"]["code"], "invalid_puzzle");
+
+        assert_eq!(parse_batch(b"{}").unwrap_err().code, ErrorCode::MalformedInput);
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let mut value = sudoku(json!({ "1:1": [1, 2], "1:2": [3] }));
+        value["constraints"]["locals"] = json!([
+            { "type": "thermometer", "cells": ["1:1", "1:2"] },
+            { "type": "x", "cells": ["1:1", "2:1"] },
+        ]);
+        let key = parse_input(value.clone()).unwrap().cache_key();
+
+        let mut same = value.clone();
+        same["domains"] = json!({ "1:2": [3], "1:1": [2, 1] });
+        same["constraints"]["locals"] = json!([value["constraints"]["locals"][1], value["constraints"]["locals"][0]]);
+        same["config"]["timeout_ms"] = json!(100);
+        assert_eq!(parse_input(same).unwrap().cache_key(), key);
+
+        let mut reversed = value.clone();
+        reversed["constraints"]["locals"][0]["cells"] = json!(["1:2", "1:1"]);
+        assert_ne!(parse_input(reversed).unwrap().cache_key(), key);
+        let mut seeded = value.clone();
+        seeded["config"]["seed"] = json!(1);
+        assert_ne!(parse_input(seeded).unwrap().cache_key(), key);
+    }
+
+    #[test]
+    fn test_api_version() {
+        let line = "48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....";
+        let mut input = sudoku(json!(sudoku::text::parse(line).unwrap()));
+        assert!(parse_input(input.clone()).is_ok());
+        input["api_version"] = json!(API_VERSION);
+        assert!(parse_input(input.clone()).is_ok());
+
+        input["api_version"] = json!(API_VERSION + 1);
+        let error = parse_input(input.clone()).unwrap_err();
+        assert_eq!(error.code, ErrorCode::Unsupported);
+        assert_eq!(error.field, Some("api_version".to_string()));
+        input["api_version"] = json!("1");
+        assert_eq!(parse_input(input).unwrap_err().code, ErrorCode::MalformedInput);
+    }
+
+    #[test]
+    fn test_schema() {
+        let line = "48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....";
+        let mut input = sudoku(json!(sudoku::text::parse(line).unwrap()));
+        input["constraints"]["locals"] = json!([{ "type": "thermometer", "cells": ["1:1", "1:2"] }]);
+        input["api_version"] = json!(API_VERSION);
+        let schema = jsonschema::validator_for(&input_schema()).unwrap();
+        assert!(schema.is_valid(&input));
+
+        // Requests the parser rejects for their shape
+        for (field, value) in [("api_version", json!(API_VERSION + 1)), ("type", json!("kakuro")), ("config", json!({ "greedy": false }))] {
+            let mut invalid = input.clone();
+            invalid[field] = value;
+            assert!(parse_input(invalid.clone()).is_err());
+            assert!(!schema.is_valid(&invalid));
+        }
+        let mut invalid = input.clone();
+        invalid["constraints"]["locals"][0]["type"] = json!("killer");
+        assert!(!schema.is_valid(&invalid));
+
+        let output = serde_json::to_value(solve(parse_input(input).unwrap()).unwrap()).unwrap();
+        assert!(jsonschema::validator_for(&output_schema()).unwrap().is_valid(&output));
+        let stats = &output_schema()["$defs"]["Stats"]["properties"];
+        assert!(stats["max_depth"]["description"].as_str().unwrap().starts_with("Deepest nesting of guesses"));
+        assert!(stats["guessing_rounds"]["description"].is_string());
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = serde_json::to_value(capabilities()).unwrap();
+        let puzzle = &capabilities["puzzles"]["sudoku"];
+        assert_eq!(puzzle["constraint_types"].as_array().unwrap().len(), 8);
+        assert_eq!(puzzle["constraint_types"][0], "white_kropki");
+        assert_eq!(puzzle["global_constraints"], json!(["anti_knight", "anti_king"]));
+
+        // The defaults, less the required options, are what a request gets by leaving options out
+        let config = &capabilities["config"];
+        assert_eq!(config["defaults"]["branching"], "degree");
+        assert_eq!(config["branching"].as_array().unwrap().len(), 5);
+        let mut input = sudoku(json!({}));
+        assert!(parse_input(input.clone()).is_ok());
+        for option in config["required"].as_array().unwrap() {
+            let mut input = input.clone();
+            input["config"].as_object_mut().unwrap().remove(option.as_str().unwrap());
+            assert_eq!(parse_input(input).unwrap_err().code, ErrorCode::MalformedInput);
+        }
+        input["config"] = config["defaults"].clone();
+        assert!(parse_input(input).is_ok());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let mut input = sudoku(json!({ "1:1": [1, 2], "1:2": [3] }));
+        input["constraints"]["locals"] = json!([{ "type": "killer", "cells": ["1:1", "1:2"] }]);
+        let error = parse_input(input).unwrap_err();
+        assert_eq!(error.code, ErrorCode::Unsupported);
+        assert_eq!(error.field, Some("constraints.locals[0].type".to_string()));
+
+        let mut input = sudoku(json!({ "1:1": [1, 2], "1:2": [3] }));
+        input["config"]["branching"] = json!("sideways");
+        let error = parse_input(input).unwrap_err();
+        assert_eq!(error.code, ErrorCode::MalformedInput);
+        assert_eq!(error.field, Some("config.branching".to_string()));
+    }
+
+}
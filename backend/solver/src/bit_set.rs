@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Clone,Copy,Eq,PartialEq,Debug)]
+#[derive(Clone,Copy,Eq,PartialEq,Debug,Default)]
 pub struct BitSet {
     bits: u128,
 }
@@ -9,6 +9,7 @@ pub struct BitSetIter {
     bits: u128
 }
 
+// Values must be below 128. Out of range values are a bug, so callers should validate their input first.
 impl BitSet {
 
     pub fn new() -> Self {
@@ -19,7 +20,7 @@ impl BitSet {
 
     pub fn from_bits(bits: u128) -> Self {
         BitSet{
-            bits: bits,
+            bits,
         }
     }
 
@@ -82,7 +83,7 @@ impl BitSet {
         if self.bits == 0 {
             panic!("max(0)")
         }
-        return self.iter().rev().next().unwrap();
+        return self.iter().next_back().unwrap();
     }
 
     pub fn empty(&self) -> bool { self.bits == 0 }
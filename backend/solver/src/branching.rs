@@ -0,0 +1,176 @@
+use crate::types::*;
+use crate::constraint::*;
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+
+// Which variable to guess first
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Branching {
+    // Smallest domain first, ties broken by most constraints
+    Mrv,
+    // Most constraints first, ties broken by smallest domain
+    #[default]
+    Degree,
+    // Smallest ratio of domain size to the weight of its constraints, where a constraint's weight
+    // is one more than the number of contradictions it has found
+    DomWdeg,
+    // Largest average reduction of the search space seen when guessing its values
+    Impact,
+    // Shuffled using the configured seed
+    Random,
+}
+
+// Which value to guess first
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueOrdering {
+    #[default]
+    Ascending,
+    Descending,
+    // Shuffled using the configured seed
+    Random,
+}
+
+impl Branching {
+    pub const ALL: [Branching; 5] = [Branching::Mrv, Branching::Degree, Branching::DomWdeg, Branching::Impact, Branching::Random];
+}
+
+impl ValueOrdering {
+    pub const ALL: [ValueOrdering; 3] = [ValueOrdering::Ascending, ValueOrdering::Descending, ValueOrdering::Random];
+}
+
+// SplitMix64, so that a seed gives the same order on every platform
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        return z ^ (z >> 31);
+    }
+
+    // Uniform in 0..n
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub(crate) fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+// log2 of the number of assignments left to search
+pub fn search_space(domains: &Domains) -> f64 {
+    domains.iter().map(|d| (d.len().max(1) as f64).log2()).sum()
+}
+
+pub struct Brancher {
+    branching: Branching,
+    value_ordering: ValueOrdering,
+    weights: Vec<usize>,
+    impacts: HashMap<(Variable, usize), (f64, usize)>,
+    rng: Rng,
+}
+
+impl Brancher {
+
+    pub fn new(branching: Branching, value_ordering: ValueOrdering, seed: u64, num_constraints: usize) -> Self {
+        Brancher {
+            branching,
+            value_ordering,
+            weights: vec![1; num_constraints],
+            impacts: HashMap::new(),
+            rng: Rng::new(seed),
+        }
+    }
+
+    // Unsolved variables, in the order they should be guessed
+    pub fn order_variables(&mut self, domains: &Domains, constraints: &Constraints) -> Vec<Variable> {
+        let mut variables = domains.iter().enumerate()
+            .filter(|(_, domain)| domain.len() > 1)
+            .map(|(v, _)| v)
+            .collect::<Vec<_>>();
+
+        let degree = |v: Variable| constraints.iter().filter(|c| c.variables().contains(v)).count();
+
+        match self.branching {
+            Branching::Mrv => {
+                variables.sort_by_key(|v| std::cmp::Reverse(degree(*v)));
+                variables.sort_by_key(|v| domains[*v].len());
+            },
+            Branching::Degree => {
+                variables.sort_by_key(|v| domains[*v].len());
+                variables.sort_by_key(|v| std::cmp::Reverse(degree(*v)));
+            },
+            Branching::DomWdeg => {
+                let score = |v: Variable| {
+                    let wdeg : usize = constraints.iter()
+                        .filter(|c| c.variables().contains(v))
+                        .map(|c| self.weights[c.id()])
+                        .sum();
+                    domains[v].len() as f64 / wdeg.max(1) as f64
+                };
+                variables.sort_by(|v1, v2| score(*v1).total_cmp(&score(*v2)));
+            },
+            Branching::Impact => {
+                // Values not guessed yet count as no impact, so fall back to MRV until impacts are known
+                let score = |v: Variable| -> f64 {
+                    domains[v].iter()
+                        .filter_map(|value| self.impacts.get(&(v, value)))
+                        .map(|(total, count)| total / *count as f64)
+                        .sum()
+                };
+                variables.sort_by_key(|v| domains[*v].len());
+                variables.sort_by(|v1, v2| score(*v2).total_cmp(&score(*v1)));
+            },
+            Branching::Random => {
+                self.rng.shuffle(&mut variables);
+            },
+        }
+
+        return variables;
+    }
+
+    // Values of a domain, in the order they should be guessed
+    pub fn order_values(&mut self, domain: Domain) -> Vec<usize> {
+        let mut values = domain.iter().collect::<Vec<_>>();
+        match self.value_ordering {
+            ValueOrdering::Ascending => {},
+            ValueOrdering::Descending => values.reverse(),
+            ValueOrdering::Random => self.rng.shuffle(&mut values),
+        }
+        return values;
+    }
+
+    pub fn record_failure(&mut self, id: ConstraintID) {
+        self.weights[id] += 1;
+    }
+
+    // Impact of a guess is the fraction of the search space it eliminated (1 for a contradiction)
+    pub fn record_impact(&mut self, variable: Variable, value: usize, before: f64, after: Option<f64>) {
+        let impact = match after {
+            Some(after) => 1.0 - (after - before).exp2(),
+            None => 1.0,
+        };
+        let entry = self.impacts.entry((variable, value)).or_insert((0.0, 0));
+        entry.0 += impact;
+        entry.1 += 1;
+    }
+
+}
@@ -0,0 +1,157 @@
+// Results of recent solves, so that solving the same puzzle again is instant.
+//
+// Results are looked up by the hash of `api::Input::cache_key`, and only returned if the canonical form
+// matches too. The least recently used are evicted once there are `capacity` of them. Results cut short
+// by a limit aren't kept, since another try might get further.
+
+use solver::api;
+use solver::SolveResult;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+
+#[derive(Default)]
+struct Lru {
+    // Canonical form, output and when it was last used, by hash
+    entries: HashMap<u64, (String, api::Output, u64)>,
+    // Hashes by when they were last used
+    uses: BTreeMap<u64, u64>,
+    clock: u64,
+}
+
+pub struct Cache {
+    lru: Mutex<Lru>,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Lru {
+
+    fn touch(&mut self, key: u64) {
+        self.clock += 1;
+        if let Some((_, _, used)) = self.entries.get_mut(&key) {
+            self.uses.remove(used);
+            *used = self.clock;
+            self.uses.insert(self.clock, key);
+        }
+    }
+
+}
+
+impl Cache {
+
+    pub fn new(capacity: usize) -> Self {
+        Cache {
+            lru: Mutex::new(Lru::default()),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &api::CacheKey) -> Option<api::Output> {
+        let mut lru = self.lru.lock().unwrap();
+        let output = match lru.entries.get(&key.hash) {
+            Some((canonical, output, _)) if *canonical == key.canonical => Some(output.clone()),
+            _ => None,
+        };
+        if output.is_some() {
+            lru.touch(key.hash);
+        }
+        let counter = if output.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        return output;
+    }
+
+    // Replaces any result for another puzzle with the same hash
+    pub fn insert(&self, key: &api::CacheKey, output: &api::Output) {
+        let api::Output::Sudoku(sudoku_output) = output;
+        if self.capacity == 0 || sudoku_output.result == SolveResult::Timeout {
+            return;
+        }
+        let mut lru = self.lru.lock().unwrap();
+        if let Some((_, _, used)) = lru.entries.insert(key.hash, (key.canonical.clone(), output.clone(), 0)) {
+            lru.uses.remove(&used);
+        }
+        lru.touch(key.hash);
+        while lru.entries.len() > self.capacity {
+            let (_, oldest) = lru.uses.pop_first().unwrap();
+            lru.entries.remove(&oldest);
+        }
+    }
+
+    // Lookups that found a result, and those that didn't
+    pub fn counts(&self) -> (u64, u64) {
+        (self.hits.load(Ordering::Relaxed), self.misses.load(Ordering::Relaxed))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use solver::puzzles::sudoku;
+    use solver::Stats;
+
+    fn output(result: SolveResult) -> api::Output {
+        api::Output::Sudoku(sudoku::api::Output {
+            domains: sudoku::api::Domains::new(),
+            result,
+            duration_ms: 0,
+            stats: Stats::default(),
+        })
+    }
+
+    fn key(hash: u64, canonical: &str) -> api::CacheKey {
+        api::CacheKey { hash, canonical: canonical.to_string() }
+    }
+
+    #[test]
+    fn test_eviction() {
+        let cache = Cache::new(2);
+        cache.insert(&key(1, "1"), &output(SolveResult::Solved));
+        cache.insert(&key(2, "2"), &output(SolveResult::Stuck));
+        assert!(cache.get(&key(1, "1")).is_some());
+        cache.insert(&key(3, "3"), &output(SolveResult::Solved));
+        // 2 was used least recently
+        assert!(cache.get(&key(2, "2")).is_none());
+        assert!(cache.get(&key(1, "1")).is_some());
+        assert!(cache.get(&key(3, "3")).is_some());
+        assert_eq!(cache.counts(), (3, 1));
+    }
+
+    #[test]
+    fn test_limits() {
+        let cache = Cache::new(2);
+        cache.insert(&key(1, "1"), &output(SolveResult::Timeout));
+        assert!(cache.get(&key(1, "1")).is_none());
+
+        let cache = Cache::new(0);
+        cache.insert(&key(1, "1"), &output(SolveResult::Solved));
+        assert!(cache.get(&key(1, "1")).is_none());
+    }
+
+    #[test]
+    fn test_collision() {
+        let cache = Cache::new(2);
+        cache.insert(&key(1, "a"), &output(SolveResult::Solved));
+        // Another puzzle with the same hash doesn't get its result
+        assert!(cache.get(&key(1, "b")).is_none());
+        assert!(cache.get(&key(1, "a")).is_some());
+        assert_eq!(cache.counts(), (1, 1));
+
+        // and replaces it when solved
+        cache.insert(&key(1, "b"), &output(SolveResult::Stuck));
+        assert!(cache.get(&key(1, "a")).is_none());
+        assert!(cache.get(&key(1, "b")).is_some());
+        cache.insert(&key(2, "c"), &output(SolveResult::Solved));
+        cache.insert(&key(3, "d"), &output(SolveResult::Solved));
+        assert!(cache.get(&key(1, "b")).is_none());
+        assert!(cache.get(&key(2, "c")).is_some());
+    }
+
+}
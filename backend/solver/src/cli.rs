@@ -0,0 +1,497 @@
+use solver::api::{self, parse_input, solve};
+use solver::{Error, ErrorCode};
+use solver::puzzles::sudoku::{self, file, fpuzzles, generator, reducer, symmetry, text};
+use solver::{Config, SolveResult};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::{Args, ValueEnum};
+
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// JSON for JSON input, lines for text input
+    Auto,
+    /// One JSON output per line
+    Json,
+    /// One 81 character line per puzzle, with '.' for unsolved cells
+    Line,
+    /// 9 rows of 9 cells per puzzle, followed by a blank line
+    Grid,
+}
+
+#[derive(Args, Debug)]
+pub struct SolveArgs {
+    /// Files of puzzles to solve, or '-' for stdin (the default).
+    /// Each file is either JSON (puzzle files, or one or more puzzle objects, or arrays of them), text (81 character lines and/or 9 line grids),
+    /// or f-puzzles links, one per line
+    files: Vec<PathBuf>,
+
+    /// How to write the results
+    #[arg(long, value_enum, default_value_t = Format::Auto)]
+    format: Format,
+
+    /// Stop at the first solution found when solving grids
+    #[arg(long)]
+    greedy: bool,
+
+    /// Upper bound on the solve time of each puzzle, in milliseconds
+    #[arg(long)]
+    timeout_ms: Option<u64>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerateFormat {
+    /// One JSON object per line: a puzzle file, plus the solution, rating and seed
+    Json,
+    /// One 81 character line per puzzle, with '.' for blank cells (losing any variant constraints)
+    Line,
+}
+
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    /// Number of puzzles to generate
+    #[arg(long, default_value_t = 1)]
+    count: usize,
+
+    /// Target difficulty: easy, medium, hard or expert
+    #[arg(long, value_parser = parse_json_string::<generator::Difficulty>, default_value = "medium")]
+    difficulty: generator::Difficulty,
+
+    /// Digits a knight's move apart can't be the same
+    #[arg(long)]
+    anti_knight: bool,
+
+    /// Digits a king's move apart can't be the same
+    #[arg(long)]
+    anti_king: bool,
+
+    /// Constraints to place at random, as TYPE=COUNT, e.g. thermometer=2 or white_kropki=4
+    #[arg(long = "constraint", value_parser = parse_random_constraint)]
+    constraints: Vec<(sudoku::api::ConstraintType, usize)>,
+
+    /// Seed for the first puzzle, incremented for each one after (defaults to the time)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Solution grids to try per puzzle before settling for the closest difficulty
+    #[arg(long, default_value_t = 20)]
+    attempts: usize,
+
+    /// How to write the puzzles
+    #[arg(long, value_enum, default_value_t = GenerateFormat::Json)]
+    format: GenerateFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct ReduceArgs {
+    /// Files of uniquely solvable puzzles to reduce, or '-' for stdin (the default), in any format `solve` reads
+    files: Vec<PathBuf>,
+
+    /// How to write the reduced puzzles
+    #[arg(long, value_enum, default_value_t = GenerateFormat::Json)]
+    format: GenerateFormat,
+
+    /// Seed to shuffle the order givens are removed in (defaults to row-major order)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Upper bound on each uniqueness check, in milliseconds. Givens whose checks hit it are kept
+    #[arg(long)]
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+pub struct CanonicalArgs {
+    /// Files of puzzles, or '-' for stdin (the default), in any format `solve` reads
+    files: Vec<PathBuf>,
+
+    /// How to write the canonical forms
+    #[arg(long, value_enum, default_value_t = GenerateFormat::Json)]
+    format: GenerateFormat,
+
+    /// Only write the first of each set of duplicates
+    #[arg(long)]
+    unique: bool,
+}
+
+// A value named as it is in the JSON API, e.g. "white_kropki"
+fn parse_json_string<T: serde::de::DeserializeOwned>(value: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::from(value)).map_err(|err| err.to_string())
+}
+
+fn parse_random_constraint(value: &str) -> Result<(sudoku::api::ConstraintType, usize), String> {
+    let (r#type, count) = value.split_once('=').ok_or("expected TYPE=COUNT")?;
+    let count = count.parse::<usize>().map_err(|err| err.to_string())?;
+    return Ok((parse_json_string(r#type)?, count));
+}
+
+// A puzzle read from a file, and whether it came from text
+struct Puzzle {
+    source: String,
+    input: Result<api::Input, Error>,
+    text: bool,
+}
+
+fn read_source(path: &PathBuf) -> io::Result<String> {
+    if path.as_os_str() == "-" {
+        let mut contents = String::new();
+        io::stdin().read_to_string(&mut contents)?;
+        return Ok(contents);
+    }
+    return fs::read_to_string(path);
+}
+
+// Puzzle files carry a version instead of a type and config
+fn parse_json(value: serde_json::Value, config: Config) -> Result<api::Input, Error> {
+    if value.get("version").is_some() {
+        return file::read_value(value).map(|file| api::Input::Sudoku(file.into_input(config)));
+    }
+    return parse_input(value);
+}
+
+fn read_json(name: &str, contents: &str, config: Config, puzzles: &mut Vec<Puzzle>) {
+    let stream = serde_json::Deserializer::from_str(contents).into_iter::<serde_json::Value>();
+    for (i, value) in stream.enumerate() {
+        let source = format!("{}#{}", name, i + 1);
+        match value {
+            Ok(serde_json::Value::Array(values)) => {
+                for (j, value) in values.into_iter().enumerate() {
+                    let source = format!("{}[{}]", source, j);
+                    puzzles.push(Puzzle { source, input: parse_json(value, config), text: false });
+                }
+            },
+            Ok(value) => {
+                puzzles.push(Puzzle { source, input: parse_json(value, config), text: false });
+            },
+            Err(err) => {
+                // The rest of the stream can't be trusted after a syntax error
+                let input = Err(Error::new(ErrorCode::MalformedInput, err.to_string()));
+                puzzles.push(Puzzle { source, input, text: false });
+                return;
+            },
+        }
+    }
+}
+
+fn read_text(name: &str, contents: &str, config: Config, puzzles: &mut Vec<Puzzle>) {
+    for (line, domains) in text::parse_all(contents) {
+        let source = format!("{}:{}", name, line);
+        let input = domains.map(|domains| api::Input::Sudoku(sudoku::api::Input {
+            domains,
+            constraints: sudoku::api::Constraints::default(),
+            config,
+        }));
+        puzzles.push(Puzzle { source, input, text: true });
+    }
+}
+
+// f-puzzles links or data, one per line, rejecting any puzzle with features the solver can't handle
+fn read_links(name: &str, contents: &str, config: Config, puzzles: &mut Vec<Puzzle>) {
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let source = format!("{}:{}", name, i + 1);
+        let input = fpuzzles::import(line).and_then(|import| import.strict()).map(|mut input| {
+            input.config = config;
+            api::Input::Sudoku(input)
+        });
+        puzzles.push(Puzzle { source, input, text: true });
+    }
+}
+
+fn is_link(contents: &str) -> bool {
+    let first = contents.lines().map(str::trim).find(|line| !line.is_empty() && !line.starts_with('#'));
+    return first.is_some_and(|line| line.contains("://") || line.starts_with("fpuzzles"));
+}
+
+// Read every puzzle in the files (stdin if there are none), and whether any file couldn't be read
+fn read_puzzles(files: Vec<PathBuf>, config: Config) -> (Vec<Puzzle>, bool) {
+    let files = if files.is_empty() { vec![PathBuf::from("-")] } else { files };
+
+    let mut puzzles = Vec::new();
+    let mut failed = false;
+    for path in files.iter() {
+        let name = path.display().to_string();
+        match read_source(path) {
+            Err(err) => {
+                eprintln!("{}: {}", name, err);
+                failed = true;
+            },
+            Ok(contents) => {
+                if contents.trim_start().starts_with(['{', '[']) {
+                    read_json(&name, &contents, config, &mut puzzles);
+                } else if is_link(&contents) {
+                    read_links(&name, &contents, config, &mut puzzles);
+                } else {
+                    read_text(&name, &contents, config, &mut puzzles);
+                }
+            },
+        }
+    }
+
+    return (puzzles, failed);
+}
+
+// Solve every puzzle, returning the process exit code: 0 if every puzzle was solved, 1 otherwise
+pub fn run(args: SolveArgs) -> i32 {
+    let config = Config {
+        greedy: args.greedy,
+        ..Config::default()
+    };
+
+    let (puzzles, mut failed) = read_puzzles(args.files, config);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for puzzle in puzzles {
+        let output = puzzle.input.and_then(|mut input| {
+            input.limit(args.timeout_ms);
+            solve(input)
+        });
+        let output = match output {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("{}: {}", puzzle.source, err);
+                failed = true;
+                continue;
+            }
+        };
+        let api::Output::Sudoku(sudoku_output) = &output;
+        if !matches!(sudoku_output.result, SolveResult::Solved) {
+            eprintln!("{}: {:?}", puzzle.source, sudoku_output.result);
+            failed = true;
+        }
+        let format = match args.format {
+            Format::Auto if puzzle.text => Format::Line,
+            Format::Auto => Format::Json,
+            format => format,
+        };
+        let written = match format {
+            Format::Line => writeln!(out, "{}", text::to_line(&sudoku_output.domains)),
+            Format::Grid => writeln!(out, "{}", text::to_grid(&sudoku_output.domains)),
+            _ => serde_json::to_writer(&mut out, &output).map_err(io::Error::from).and_then(|_| writeln!(out)),
+        };
+        if let Err(err) = written {
+            eprintln!("{}", err);
+            return 1;
+        }
+    }
+
+    return if failed { 1 } else { 0 };
+}
+
+// Generate puzzles, returning the process exit code: 0 if every puzzle was generated, 1 otherwise
+pub fn generate(args: GenerateArgs) -> i32 {
+    let seed = args.seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64));
+    let mut random = BTreeMap::new();
+    for (r#type, count) in args.constraints {
+        *random.entry(r#type).or_insert(0) += count;
+    }
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut failed = false;
+    for i in 0..args.count {
+        let request = generator::Request {
+            globals: sudoku::api::GlobalConstraints { anti_knight: args.anti_knight, anti_king: args.anti_king },
+            locals: Vec::new(),
+            random: random.clone(),
+            difficulty: args.difficulty,
+            seed: seed.wrapping_add(i as u64),
+            attempts: args.attempts,
+        };
+        let generated = match generator::generate(&request) {
+            Ok(generated) => generated,
+            Err(err) => {
+                eprintln!("seed {}: {}", request.seed, err);
+                failed = true;
+                continue;
+            },
+        };
+        if generated.difficulty != args.difficulty {
+            eprintln!("seed {}: closest difficulty found was {:?}", request.seed, generated.difficulty);
+        }
+        let written = match args.format {
+            GenerateFormat::Line => writeln!(out, "{}", text::to_line(&generated.puzzle.domains)),
+            GenerateFormat::Json => serde_json::to_writer(&mut out, &generated).map_err(io::Error::from).and_then(|_| writeln!(out)),
+        };
+        if let Err(err) = written {
+            eprintln!("{}", err);
+            return 1;
+        }
+    }
+
+    return if failed { 1 } else { 0 };
+}
+
+// Reduce every puzzle to minimal givens, returning the process exit code: 0 if every puzzle was reduced, 1 otherwise
+pub fn reduce(args: ReduceArgs) -> i32 {
+    let (puzzles, mut failed) = read_puzzles(args.files, Config::default());
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for puzzle in puzzles {
+        let reduced = puzzle.input.and_then(|mut input| {
+            input.limit(args.timeout_ms);
+            let api::Input::Sudoku(input) = input;
+            reducer::reduce(&input, args.seed)
+        });
+        let reduced = match reduced {
+            Ok(reduced) => reduced,
+            Err(err) => {
+                eprintln!("{}: {}", puzzle.source, err);
+                failed = true;
+                continue;
+            },
+        };
+        if !reduced.undecided.is_empty() {
+            eprintln!("{}: kept {} givens that couldn't be checked in time", puzzle.source, reduced.undecided.len());
+        }
+        let written = match args.format {
+            GenerateFormat::Line => writeln!(out, "{}", text::to_line(&reduced.puzzle.domains)),
+            GenerateFormat::Json => serde_json::to_writer(&mut out, &reduced).map_err(io::Error::from).and_then(|_| writeln!(out)),
+        };
+        if let Err(err) = written {
+            eprintln!("{}", err);
+            return 1;
+        }
+    }
+
+    return if failed { 1 } else { 0 };
+}
+
+// Write the canonical form of every puzzle, reporting duplicates (puzzles with the same canonical form as an
+// earlier one). Returns the process exit code: 0 if every puzzle was read, 1 otherwise
+pub fn canonical(args: CanonicalArgs) -> i32 {
+    let (puzzles, mut failed) = read_puzzles(args.files, Config::default());
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut seen = HashMap::new();
+    for puzzle in puzzles {
+        let canonical = puzzle.input.and_then(|input| {
+            let api::Input::Sudoku(input) = input;
+            symmetry::canonical(&input.domains, &input.constraints)
+        });
+        let canonical = match canonical {
+            Ok(canonical) => canonical,
+            Err(err) => {
+                eprintln!("{}: {}", puzzle.source, err);
+                failed = true;
+                continue;
+            },
+        };
+        if let Some(first) = seen.get(&canonical.key()) {
+            eprintln!("{}: duplicate of {}", puzzle.source, first);
+            if args.unique {
+                continue;
+            }
+        } else {
+            seen.insert(canonical.key(), puzzle.source.clone());
+        }
+        let written = match args.format {
+            GenerateFormat::Line => writeln!(out, "{}", text::to_line(&canonical.domains)),
+            GenerateFormat::Json => {
+                let input = sudoku::api::Input { domains: canonical.domains, constraints: canonical.constraints, config: Config::default() };
+                let file = file::PuzzleFile::new(input, None);
+                serde_json::to_writer(&mut out, &file).map_err(io::Error::from).and_then(|_| writeln!(out))
+            },
+        };
+        if let Err(err) = written {
+            eprintln!("{}", err);
+            return 1;
+        }
+    }
+
+    return if failed { 1 } else { 0 };
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    const LINE : &str = "48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....";
+
+    fn sudoku() -> sudoku::api::Input {
+        sudoku::api::Input {
+            domains: text::parse(LINE).unwrap(),
+            constraints: sudoku::api::Constraints::default(),
+            config: Config::default(),
+        }
+    }
+
+    fn codes(puzzles: &[Puzzle]) -> Vec<Option<ErrorCode>> {
+        return puzzles.iter().map(|puzzle| puzzle.input.as_ref().err().map(|err| err.code)).collect();
+    }
+
+    #[test]
+    fn test_read_json() {
+        let file = json!(file::PuzzleFile::new(sudoku(), None));
+        // The same puzzle as a request, with a type and config instead of a version
+        let mut request = file.clone();
+        request.as_object_mut().unwrap().remove("version");
+        request["type"] = json!("sudoku");
+        request["config"] = json!({ "greedy": false, "breadcrumbs": false });
+        let contents = format!("{}\n[{}, {{\"type\": \"kakuro\"}}]\n{{ not json", file, request);
+
+        let mut puzzles = Vec::new();
+        read_json("in", &contents, Config::default(), &mut puzzles);
+        let sources = puzzles.iter().map(|puzzle| puzzle.source.as_str()).collect::<Vec<_>>();
+        assert_eq!(sources, vec!["in#1", "in#2[0]", "in#2[1]", "in#3"]);
+        assert_eq!(codes(&puzzles), vec![None, None, Some(ErrorCode::Unsupported), Some(ErrorCode::MalformedInput)]);
+        assert!(puzzles.iter().all(|puzzle| !puzzle.text));
+    }
+
+    #[test]
+    fn test_is_link() {
+        let url = fpuzzles::to_url(&sudoku()).unwrap();
+        assert!(is_link(&url));
+        assert!(is_link(&format!("# links\n\n{}\n", url)));
+        assert!(is_link("fpuzzlesN4IgzglgXgpiBcBOANCA"));
+        assert!(!is_link(LINE));
+        assert!(!is_link("# only a comment\n"));
+    }
+
+    #[test]
+    fn test_read_puzzles() {
+        let dir = std::env::temp_dir().join(format!("solver-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = [
+            ("puzzle.json", json!(file::PuzzleFile::new(sudoku(), None)).to_string()),
+            ("links.txt", format!("# from f-puzzles\n{}\n", fpuzzles::to_url(&sudoku()).unwrap())),
+            ("lines.txt", format!("{}\n\n{}\n", LINE, LINE.replace('.', "0"))),
+            ("other.txt", "not a puzzle\n".to_string()),
+        ];
+        let mut paths = files.iter().map(|(name, contents)| {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        }).collect::<Vec<_>>();
+        paths.push(dir.join("missing.txt"));
+
+        let (puzzles, failed) = read_puzzles(paths, Config::default());
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(failed);
+
+        let sources = puzzles.iter().map(|puzzle| puzzle.source.rsplit(std::path::MAIN_SEPARATOR).next().unwrap()).collect::<Vec<_>>();
+        assert_eq!(sources, vec!["puzzle.json#1", "links.txt:2", "lines.txt:1", "lines.txt:3", "other.txt:1"]);
+        assert_eq!(codes(&puzzles), vec![None, None, None, None, Some(ErrorCode::InvalidPuzzle)]);
+        assert_eq!(puzzles.iter().map(|puzzle| puzzle.text).collect::<Vec<_>>(), vec![false, true, true, true, true]);
+
+        // Every format reads as the same puzzle
+        for puzzle in &puzzles[..4] {
+            let api::Input::Sudoku(input) = puzzle.input.as_ref().unwrap();
+            assert_eq!(input.domains, sudoku().domains);
+        }
+    }
+
+}
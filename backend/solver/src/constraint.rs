@@ -14,12 +14,54 @@ pub enum SimplifyResult {
     Rewrite(Constraints),
 }
 
-pub trait Constraint : std::fmt::Debug {
+// Kinds of constraint, for statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstraintKind {
+    Permutation,
+    Equals,
+    NotEquals,
+    ConsecutiveSet,
+    Increasing,
+    Difference,
+    Ratio,
+    DistinctSum,
+}
 
-    fn clone_box(&self) -> Box<dyn Constraint>;
+impl ConstraintKind {
+
+    pub const ALL: [ConstraintKind; 8] = [
+        ConstraintKind::Permutation,
+        ConstraintKind::Equals,
+        ConstraintKind::NotEquals,
+        ConstraintKind::ConsecutiveSet,
+        ConstraintKind::Increasing,
+        ConstraintKind::Difference,
+        ConstraintKind::Ratio,
+        ConstraintKind::DistinctSum,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConstraintKind::Permutation    => "permutation",
+            ConstraintKind::Equals         => "equals",
+            ConstraintKind::NotEquals      => "not_equals",
+            ConstraintKind::ConsecutiveSet => "consecutive_set",
+            ConstraintKind::Increasing     => "increasing",
+            ConstraintKind::Difference     => "difference",
+            ConstraintKind::Ratio          => "ratio",
+            ConstraintKind::DistinctSum    => "distinct_sum",
+        }
+    }
+
+}
+
+pub trait Constraint : std::fmt::Debug {
 
     fn id(&self) -> ConstraintID;
 
+    // kind of constraint, for statistics
+    fn kind(&self) -> ConstraintKind;
+
     fn variables(&self) -> &VariableSet;
 
     // provided not all variables are solved, and no variable is unsolvable,
@@ -62,23 +104,21 @@ pub trait Constraint : std::fmt::Debug {
     }
 }
 
-impl Clone for Box<dyn Constraint> {
-    fn clone(&self) -> Self { self.clone_box() }
-}
-
 pub fn apply<C, F>(constraint: &C, domains: &mut Domains, reporter: &dyn Reporter, variable: Variable, fun: F) -> bool
 where
     C: Constraint,
     F: Fn(&mut Domain)
 {
-    let new = &mut domains[variable];
-    let old = *new;
-    fun(new);
-    if *new == old {
+    // Only write changed domains, so that the trail stays short
+    let old = domains[variable];
+    let mut new = old;
+    fun(&mut new);
+    if new == old {
         return false;
     } else {
+        domains[variable] = new;
         if reporter.enabled() {
-            reporter.emit(format!("{} is not {} by {}", reporter.variable_name(variable), old.difference(*new), reporter.constraint_name(constraint.id())));
+            reporter.emit(format!("{} is not {} by {}", reporter.variable_name(variable), old.difference(new), reporter.constraint_name(constraint.id())));
         }
         return true;
     }
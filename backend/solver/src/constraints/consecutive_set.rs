@@ -11,22 +11,20 @@ pub struct ConsecutiveSet {
 
 impl ConsecutiveSet {
 
-    pub fn new(id: ConstraintID, variables: VariableSet) -> Self {
+    pub fn new(id: ConstraintID, variables: VariableSet) -> Result<Self, String> {
         if variables.len() <= 1 {
-            panic!("bad ConsecutiveSet")
+            return Err("consecutive set needs at least two cells".to_string());
         }
-        return ConsecutiveSet {
+        return Ok(ConsecutiveSet {
             id,
             variables,
-        };
+        });
     }
 
 }
 
 impl Constraint for ConsecutiveSet {
 
-    fn clone_box(&self) -> Box<dyn Constraint> { Box::new(self.clone()) }
-
     fn check_solved(&self, domains: &mut Domains) -> bool {
         let union : Domain = self.variables.iter().map(|v| domains[v]).union();
         return union.len() == self.variables.len() && (union.max() - union.min() + 1) == union.len();
@@ -97,4 +95,8 @@ impl Constraint for ConsecutiveSet {
     fn id(&self) -> ConstraintID {
         self.id
     }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::ConsecutiveSet
+    }
 }
@@ -13,17 +13,23 @@ pub struct Difference {
 
 impl Difference {
 
-    pub fn new(id: ConstraintID, variables: Vec<Variable>, threshold: usize) -> Self {
+    pub fn new(id: ConstraintID, variables: Vec<Variable>, threshold: usize) -> Result<Self, String> {
         if variables.len() <= 1 {
-            panic!("bad Difference")
+            return Err("difference needs at least two cells".to_string());
         }
-        let variable_set = variables.iter().map(|v| Domain::single(*v)).union();
-        return Difference {
+        if threshold == 0 {
+            return Err("difference needs a positive threshold".to_string());
+        }
+        let variable_set : VariableSet = variables.iter().map(|v| VariableSet::single(*v)).union();
+        if variable_set.len() != variables.len() {
+            return Err("difference can't repeat a cell".to_string());
+        }
+        return Ok(Difference {
             id,
             variables,
             variable_set,
-            threshold: threshold,
-        };
+            threshold,
+        });
     }
 
 }
@@ -37,8 +43,6 @@ fn difference(domain: Domain, threshold: usize) -> Domain {
 
 impl Constraint for Difference {
 
-    fn clone_box(&self) -> Box<dyn Constraint> { Box::new(self.clone()) }
-
     fn check_solved(&self, domains: &mut Domains) -> bool {
         let mut last : Option<usize> = None;
         for variable in self.variables.iter() {
@@ -97,5 +101,9 @@ impl Constraint for Difference {
     fn id(&self) -> ConstraintID {
         self.id
     }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Difference
+    }
 }
 
@@ -12,23 +12,21 @@ pub struct DistinctSum {
 
 impl DistinctSum {
 
-    pub fn new(id: ConstraintID, variables: VariableSet, sum: usize) -> Self {
-        if variables.len() == 0 {
-            panic!("bad DistinctSum");
+    pub fn new(id: ConstraintID, variables: VariableSet, sum: usize) -> Result<Self, String> {
+        if variables.empty() {
+            return Err("sum needs at least one cell".to_string());
         }
-        return DistinctSum {
+        return Ok(DistinctSum {
             id,
             variables,
             sum,
-        };
+        });
     }
 
 }
 
 impl Constraint for DistinctSum {
 
-    fn clone_box(&self) -> Box<dyn Constraint> { Box::new(self.clone()) }
-
     fn check_solved(&self, domains: &mut Domains) -> bool {
         let union : Domain = self.variables.iter().map(|v| domains[v]).union();
         return union.len() == self.variables.len() && self.sum == union.iter().sum::<usize>();
@@ -63,8 +61,9 @@ impl Constraint for DistinctSum {
                 for variable in v2.iter() {
                     domains[variable].difference_with(d1);
                 }
-                let c1 = Box::new(Permutation::new(self.id, v1, d1));
-                let c2 = Box::new(DistinctSum::new(self.id, v2, self.sum - sum));
+                // v1 is a strict subset, so v2 isn't empty
+                let c1 = Box::new(Permutation::new(self.id, v1, d1).unwrap());
+                let c2 = Box::new(DistinctSum::new(self.id, v2, self.sum - sum).unwrap());
                 return SimplifyResult::Rewrite(vec![c1, c2]);
             }
             _ => {
@@ -73,7 +72,7 @@ impl Constraint for DistinctSum {
                 let union: Domain = self.variables.iter().map(|v| domains[v]).union();
                 if union.len() == self.variables.len() {
                     if self.sum == union.iter().sum::<usize>() {
-                       let constraint = Box::new(Permutation::new(self.id, self.variables, union));
+                       let constraint = Box::new(Permutation::new(self.id, self.variables, union).unwrap());
                        return SimplifyResult::Rewrite(vec![constraint]);
                     } else {
                         return SimplifyResult::Unsolvable;
@@ -92,5 +91,9 @@ impl Constraint for DistinctSum {
     fn id(&self) -> ConstraintID {
         self.id
     }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::DistinctSum
+    }
 }
 
@@ -10,22 +10,20 @@ pub struct Equals {
 
 impl Equals {
 
-    pub fn new(id: ConstraintID, variables: VariableSet) -> Self {
+    pub fn new(id: ConstraintID, variables: VariableSet) -> Result<Self, String> {
         if variables.len() <= 1 {
-            panic!("bad Equals")
+            return Err("equals needs at least two cells".to_string());
         }
-        return Equals {
+        return Ok(Equals {
             id,
             variables,
-        };
+        });
     }
 
 }
 
 impl Constraint for Equals {
 
-    fn clone_box(&self) -> Box<dyn Constraint> { Box::new(self.clone()) }
-
     fn check_solved(&self, domains: &mut Domains) -> bool {
         let mut last : Option<usize> = None;
         for variable in self.variables.iter() {
@@ -62,5 +60,9 @@ impl Constraint for Equals {
     fn id(&self) -> ConstraintID {
         self.id
     }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Equals
+    }
 }
 
@@ -11,27 +11,28 @@ pub struct Increasing {
 
 impl Increasing {
 
-    pub fn new(id: ConstraintID, variables: Vec<Variable>) -> Self {
+    pub fn new(id: ConstraintID, variables: Vec<Variable>) -> Result<Self, String> {
         if variables.len() <= 1 {
-            panic!("bad Increasing")
+            return Err("increasing needs at least two cells".to_string());
         }
         let mut variable_set = VariableSet::new();
         for variable in variables.iter() {
             variable_set.insert(*variable);
         }
-        return Increasing {
+        if variable_set.len() != variables.len() {
+            return Err("increasing can't repeat a cell".to_string());
+        }
+        return Ok(Increasing {
             id,
             variables,
             variable_set,
-        };
+        });
     }
 
 }
 
 impl Constraint for Increasing {
 
-    fn clone_box(&self) -> Box<dyn Constraint> { Box::new(self.clone()) }
-
     fn check_solved(&self, domains: &mut Domains) -> bool {
         let mut last : Option<usize> = None;
         for variable in self.variables.iter() {
@@ -97,5 +98,9 @@ impl Constraint for Increasing {
     fn id(&self) -> ConstraintID {
         self.id
     }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Increasing
+    }
 }
 
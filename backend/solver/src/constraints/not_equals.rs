@@ -9,22 +9,20 @@ pub struct NotEquals {
 
 impl NotEquals {
 
-    pub fn new(id: ConstraintID, variables: VariableSet) -> Self {
+    pub fn new(id: ConstraintID, variables: VariableSet) -> Result<Self, String> {
         if variables.len() != 2 { // TODO support > 2 (in the simplifier)
-            panic!("bad NotEquals")
+            return Err(format!("not equals needs exactly two cells, got {}", variables.len()));
         }
-        return NotEquals {
+        return Ok(NotEquals {
             id,
             variables,
-        };
+        });
     }
 
 }
 
 impl Constraint for NotEquals {
 
-    fn clone_box(&self) -> Box<dyn Constraint> { Box::new(self.clone()) }
-
     fn check_solved(&self, domains: &mut Domains) -> bool {
         let mut seen = Domain::new();
         for variable in self.variables.iter() {
@@ -92,5 +90,9 @@ impl Constraint for NotEquals {
     fn id(&self) -> ConstraintID {
         self.id
     }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::NotEquals
+    }
 }
 
@@ -12,15 +12,15 @@ pub struct Permutation {
 
 impl Permutation {
 
-    pub fn new(id: ConstraintID, variables: VariableSet, domain: Domain) -> Self {
+    pub fn new(id: ConstraintID, variables: VariableSet, domain: Domain) -> Result<Self, String> {
         if variables.len() != domain.len() {
-            panic!("bad Permutation: #variables != #domain")
+            return Err(format!("permutation needs as many digits as cells, got {} cells and {} digits", variables.len(), domain.len()));
         }
-        return Permutation {
+        return Ok(Permutation {
             id,
             variables,
             domain
-        };
+        });
     }
 
 }
@@ -51,8 +51,6 @@ pub fn simplify_distinct(domains: &mut Domains, variables: VariableSet) -> Optio
 
 impl Constraint for Permutation {
 
-    fn clone_box(&self) -> Box<dyn Constraint> { Box::new(self.clone()) }
-
     fn check_solved(&self, domains: &mut Domains) -> bool {
         let union: Domain = self.variables.iter().map(|v| domains[v]).union();
         return union == self.domain;
@@ -70,9 +68,10 @@ impl Constraint for Permutation {
         //match simplify_distinct(domains, self.variables).or_else(|| simplify_permutation(domains, self.variables)) {
         match simplify_distinct(domains, self.variables) {
             Some((v1, d1)) => {
-                let c1 = Box::new(Permutation::new(self.id, v1, d1));
+                // d1 is within our domain, so both halves have as many digits as cells
+                let c1 = Box::new(Permutation::new(self.id, v1, d1).unwrap());
                 let (v2, d2) = (self.variables.difference(v1), self.domain.difference(d1));
-                let c2 = Box::new(Permutation::new(self.id, v2, d2));
+                let c2 = Box::new(Permutation::new(self.id, v2, d2).unwrap());
                 return SimplifyResult::Rewrite(vec![c1, c2]);
             }
             None => {}
@@ -92,5 +91,9 @@ impl Constraint for Permutation {
     fn id(&self) -> ConstraintID {
         self.id
     }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Permutation
+    }
 }
 
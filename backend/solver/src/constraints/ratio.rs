@@ -10,15 +10,18 @@ pub struct Ratio {
 
 impl Ratio {
 
-    pub fn new(id: ConstraintID, variables: VariableSet, ratio: usize) -> Self {
-        if variables.len() != 2 || ratio == 0 {
-            panic!("bad Ratio")
+    pub fn new(id: ConstraintID, variables: VariableSet, ratio: usize) -> Result<Self, String> {
+        if variables.len() != 2 {
+            return Err(format!("ratio needs exactly two cells, got {}", variables.len()));
         }
-        return Ratio {
+        if ratio == 0 {
+            return Err("ratio can't be zero".to_string());
+        }
+        return Ok(Ratio {
             id,
             variables,
             ratio,
-        };
+        });
     }
 
 }
@@ -40,8 +43,6 @@ fn ratio_image(domain: Domain, ratio: usize) -> Domain {
 
 impl Constraint for Ratio {
 
-    fn clone_box(&self) -> Box<dyn Constraint> { Box::new(self.clone()) }
-
     fn check_solved(&self, domains: &mut Domains) -> bool {
 
         let mut iter = self.variables.iter();
@@ -93,5 +94,9 @@ impl Constraint for Ratio {
     fn id(&self) -> ConstraintID {
         self.id
     }
+
+    fn kind(&self) -> ConstraintKind {
+        ConstraintKind::Ratio
+    }
 }
 
@@ -0,0 +1,91 @@
+use serde::Serialize;
+use std::fmt;
+
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    // The request is not valid JSON, or doesn't match the schema
+    MalformedInput,
+    // The request names a puzzle or constraint type the solver doesn't know
+    Unsupported,
+    // The request is well formed, but doesn't describe a valid puzzle
+    InvalidPuzzle,
+    PayloadTooLarge,
+    NotFound,
+    // The server is too busy to take the request, so try again later
+    Unavailable,
+    Internal,
+}
+
+impl ErrorCode {
+
+    // Name as it appears in the JSON
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorCode::MalformedInput  => "malformed_input",
+            ErrorCode::Unsupported     => "unsupported",
+            ErrorCode::InvalidPuzzle   => "invalid_puzzle",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::NotFound        => "not_found",
+            ErrorCode::Unavailable     => "unavailable",
+            ErrorCode::Internal        => "internal",
+        }
+    }
+
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+    // Path to the offending part of the request, e.g. "constraints.locals[2].type"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cell: Option<String>,
+}
+
+impl Error {
+
+    pub fn new(code: ErrorCode, message: String) -> Self {
+        Error {
+            code,
+            message,
+            field: None,
+            cell: None,
+        }
+    }
+
+    pub fn invalid_puzzle(message: String) -> Self {
+        Error::new(ErrorCode::InvalidPuzzle, message)
+    }
+
+    pub fn with_field(mut self, field: String) -> Self {
+        self.field = Some(field);
+        return self;
+    }
+
+    pub fn with_cell(mut self, cell: String) -> Self {
+        self.cell = Some(cell);
+        return self;
+    }
+
+}
+
+impl fmt::Display for Error {
+
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(field) = &self.field {
+            write!(f, " (at {})", field)?;
+        }
+        if let Some(cell) = &self.cell {
+            write!(f, " (cell {})", cell)?;
+        }
+        return Ok(());
+    }
+
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,27 @@
+// Constraint solver for sudoku and its variants.
+//
+// Puzzles are converted into variables (with domains of candidate digits) and constraints over them,
+// which the Solver then simplifies, guessing when it gets stuck.
+// Use `api` to solve a puzzle from its JSON description,
+// or `puzzles::sudoku::Converter` and `Solver` to drive the engine directly.
+
+pub mod bit_set;
+pub mod types;
+pub mod constraint;
+pub mod constraints;
+pub mod solver;
+pub mod branching;
+pub mod puzzles;
+pub mod error;
+pub mod api;
+#[cfg(feature = "python")]
+mod python;
+// Also compiled for tests, which cover everything but the exports
+#[cfg(any(feature = "wasm", test))]
+mod wasm;
+
+pub use bit_set::BitSet;
+pub use types::{Domain, Domains, Variable, VariableSet, ConstraintID, Reporter};
+pub use constraint::{Constraint, Constraints, SimplifyResult};
+pub use solver::{Solver, Config, Observer, SolveResult, Solutions, Stats};
+pub use error::{Error, ErrorCode};
@@ -1,93 +1,555 @@
-mod bit_set;
-mod solver;
-mod types;
-mod constraint;
-mod constraints;
-mod puzzles;
+mod cache;
+mod cli;
+mod metrics;
+mod pool;
 
 use std::convert::Infallible;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
 
-use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
-use hyper::body::Bytes;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use http_body_util::{combinators::BoxBody, BodyExt, Full, Limited};
+use hyper::body::{Bytes, Frame};
 use hyper::server::conn::http1;
 use hyper::{Method, Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
+use serde::Serialize;
 use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tracing::level_filters::LevelFilter;
+use tracing::Instrument;
+
+use solver::api;
+use solver::{Domains, Error, ErrorCode, Observer, Reporter, Stats};
 
-use puzzles::*;
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LogLevel {
+    Error,
+    Warn,
+    /// One line per request and per solve
+    Info,
+    Debug,
+    /// Also every input
+    Trace,
+}
 
-mod api {
+impl LogLevel {
 
-use serde::Deserialize;
-use serde::Serialize;
-use crate::puzzles;
+    fn filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn  => LevelFilter::WARN,
+            LogLevel::Info  => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
 
-#[derive(Deserialize, Debug)]
-#[serde(tag = "type", rename_all = "snake_case")]
-pub enum Input {
-    Sudoku(puzzles::sudoku::api::Input)
 }
 
-#[derive(Serialize, Debug)]
-#[serde(tag = "type", rename_all = "snake_case")]
-pub enum Output {
-    Sudoku(puzzles::sudoku::api::Output)
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// One JSON object per line
+    Json,
 }
 
+#[derive(Parser, Debug)]
+#[command(about = "Solve puzzles, serving the solver over HTTP unless given a command", args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    server: ServerArgs,
 }
 
-fn solve(input: api::Input) -> Result<api::Output, String> {
-    match input {
-        api::Input::Sudoku(input_data) => match sudoku::solve(input_data) {
-            Ok(output_data) => Ok(api::Output::Sudoku(output_data)),
-            Err(e)          => Err(e),
-        }
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Solve puzzles from files (or stdin) and write the results to stdout
+    Solve(cli::SolveArgs),
+    /// Generate puzzles with a unique solution and write them to stdout
+    Generate(cli::GenerateArgs),
+    /// Remove givens from uniquely solvable puzzles until none can go, and write them to stdout
+    Reduce(cli::ReduceArgs),
+    /// Write puzzles in a canonical form, the same for puzzles that are rotations, reflections, relabellings
+    /// or other transforms of one another, and report duplicates
+    Canonical(cli::CanonicalArgs),
+}
+
+#[derive(Args, Debug)]
+struct ServerArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    address: IpAddr,
+
+    /// Port to listen on
+    #[arg(long, default_value_t = 3000)]
+    port: u16,
+
+    /// Largest request body to accept, in bytes
+    #[arg(long, default_value_t = 1 << 20)]
+    max_body_bytes: usize,
+
+    /// Number of runtime worker threads (defaults to the number of cores)
+    #[arg(long)]
+    workers: Option<NonZeroUsize>,
+
+    /// Upper bound on the solve time of any request, in milliseconds
+    #[arg(long)]
+    timeout_ms: Option<u64>,
+
+    /// Number of puzzles to solve at once (defaults to the number of cores)
+    #[arg(long)]
+    max_solves: Option<NonZeroUsize>,
+
+    /// Number of requests to queue while all solves are busy, beyond which requests get a 503
+    #[arg(long, default_value_t = 64)]
+    max_queued: usize,
+
+    /// Number of results to keep for puzzles solved again (0 to turn off caching)
+    #[arg(long, default_value_t = 1000)]
+    cache_size: usize,
+
+    /// Most verbose messages to log (to stderr)
+    #[arg(long, value_enum, default_value_t = LogLevel::Info)]
+    log_level: LogLevel,
+
+    /// How to write log messages
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
 }
 
-async fn handle_solve_request(req: Request<hyper::body::Incoming>) -> Result<Response<BoxBody<Bytes, Infallible>>, hyper::Error> {
-    if req.method() != &Method::POST || req.uri() != "/solve" {
-        return Ok(Response::builder()
-              .status(StatusCode::NOT_FOUND)
-              .body(Empty::new().boxed())
-              .unwrap())
+// Seconds to tell clients to wait when the solver is busy
+const RETRY_AFTER: &str = "1";
+
+// Server-sent events that can wait for a slow client
+const EVENT_BUFFER: usize = 64;
+
+// State shared by every request
+struct Server {
+    args: ServerArgs,
+    pool: pool::Pool,
+    cache: cache::Cache,
+    metrics: metrics::Metrics,
+    started: Instant,
+    // Id for the next request, to tie together what's logged about it
+    next_id: AtomicU64,
+}
+
+#[derive(Serialize, Debug)]
+struct Health {
+    status: &'static str,
+    uptime_s: u64,
+    // Solve requests running or queued, and how many there can be before requests are turned away
+    pending: usize,
+    capacity: usize,
+}
+
+type Body = BoxBody<Bytes, Infallible>;
+
+fn status(code: ErrorCode) -> StatusCode {
+    match code {
+        ErrorCode::MalformedInput  => StatusCode::BAD_REQUEST,
+        ErrorCode::Unsupported     => StatusCode::UNPROCESSABLE_ENTITY,
+        ErrorCode::InvalidPuzzle   => StatusCode::UNPROCESSABLE_ENTITY,
+        ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        ErrorCode::NotFound        => StatusCode::NOT_FOUND,
+        ErrorCode::Unavailable     => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::Internal        => StatusCode::INTERNAL_SERVER_ERROR,
     }
-    let input_bytes = req.collect().await?.to_bytes();
-    let input : serde_json::Result<api::Input> = serde_json::from_slice(&input_bytes);
-    match input {
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    let (status, bytes) = match serde_json::to_vec(body) {
+        Ok(bytes) => (status, bytes),
         Err(err) => {
-            eprintln!("{:?}", err);
-            Ok(Response::builder()
-                .status(StatusCode::UNPROCESSABLE_ENTITY)
-                .body(Empty::new().boxed())
-                .unwrap())
+            let error = Error::new(ErrorCode::Internal, format!("failed to serialize response: {}", err));
+            (StatusCode::INTERNAL_SERVER_ERROR, serde_json::to_vec(&error).unwrap_or_default())
         },
-        Ok(input) => {
-            println!("{:?}", input);
-            let output = solve(input);
-            match output {
-                Err(err) => {
-                    eprintln!("{:?}", err);
-                    Ok(Response::builder()
-                        .status(StatusCode::UNPROCESSABLE_ENTITY)
-                        .body(Empty::new().boxed())
-                        .unwrap())
-                },
-                Ok(output) => {
-                    println!("{:?}", output);
-                    let output_bytes = serde_json::to_vec(&output).unwrap();
-                    Ok(Response::new(Full::new(Bytes::from(output_bytes)).boxed()))
-                }
+    };
+    let mut response = Response::new(Full::new(Bytes::from(bytes)).boxed());
+    *response.status_mut() = status;
+    response.headers_mut().insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static("application/json"));
+    return response;
+}
+
+fn text_response(content_type: &'static str, body: String) -> Response<Body> {
+    let mut response = Response::new(Full::new(Bytes::from(body)).boxed());
+    response.headers_mut().insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static(content_type));
+    return response;
+}
+
+// Log and count an error returned to a client
+fn log_error(server: &Server, error: &Error) {
+    server.metrics.error(error.code.name());
+    if status(error.code).is_server_error() {
+        tracing::error!(code = error.code.name(), "{}", error);
+    } else {
+        tracing::warn!(code = error.code.name(), "{}", error);
+    }
+}
+
+fn error_response(server: &Server, error: Error) -> Response<Body> {
+    log_error(server, &error);
+    let mut response = json_response(status(error.code), &error);
+    if error.code == ErrorCode::Unavailable {
+        response.headers_mut().insert(hyper::header::RETRY_AFTER, hyper::header::HeaderValue::from_static(RETRY_AFTER));
+    }
+    return response;
+}
+
+// The body of a request, or the response to send if it's too large
+async fn read_body(server: &Server, req: Request<hyper::body::Incoming>) -> Result<Result<Bytes, Response<Body>>, hyper::Error> {
+    let max_body_bytes = server.args.max_body_bytes;
+    match Limited::new(req.into_body(), max_body_bytes).collect().await {
+        Ok(body) => Ok(Ok(body.to_bytes())),
+        Err(err) => match err.downcast::<hyper::Error>() {
+            Ok(err) => Err(*err),
+            Err(err) => {
+                let error = Error::new(ErrorCode::PayloadTooLarge, format!("{} (limit is {} bytes)", err, max_body_bytes));
+                Ok(Err(error_response(server, error)))
             }
+        },
+    }
+}
+
+// The puzzle in the body of a request, or the response to send if there isn't one
+async fn read_input(server: &Server, req: Request<hyper::body::Incoming>) -> Result<Result<api::Input, Response<Body>>, hyper::Error> {
+    let input_bytes = match read_body(server, req).await? {
+        Ok(bytes) => bytes,
+        Err(response) => return Ok(Err(response)),
+    };
+    let input = match api::parse(&input_bytes) {
+        Ok(input) => input,
+        Err(error) => return Ok(Err(error_response(server, error))),
+    };
+    tracing::trace!(?input);
+    return Ok(Ok(input));
+}
+
+// Handle a request in a span of its own, logging the response and tagging it with the request's id
+async fn handle(server: Arc<Server>, req: Request<hyper::body::Incoming>) -> Result<Response<Body>, hyper::Error> {
+    let id = server.next_id.fetch_add(1, Ordering::Relaxed);
+    let span = tracing::info_span!("request", id, method = %req.method(), path = %req.uri().path());
+    async move {
+        let started = Instant::now();
+        let mut response = handle_request(server, req).await?;
+        tracing::info!(status = response.status().as_u16(), duration_ms = started.elapsed().as_millis() as u64, "responded");
+        response.headers_mut().insert("x-request-id", hyper::header::HeaderValue::from(id));
+        Ok(response)
+    }.instrument(span).await
+}
+
+async fn handle_request(server: Arc<Server>, req: Request<hyper::body::Incoming>) -> Result<Response<Body>, hyper::Error> {
+    match (req.method(), req.uri().path()) {
+        (&Method::POST, "/solve") => handle_solve_request(server, req).await,
+        (&Method::POST, "/solve_stream") => handle_solve_stream_request(server, req).await,
+        (&Method::POST, "/solve_batch") => handle_solve_batch_request(server, req).await,
+        (&Method::POST, "/share") => handle_share_request(&server, req).await,
+        (&Method::GET, "/health") => Ok(json_response(StatusCode::OK, &health(&server))),
+        (&Method::GET, "/version") => Ok(json_response(StatusCode::OK, &api::version())),
+        (&Method::GET, "/capabilities") => Ok(json_response(StatusCode::OK, &api::capabilities())),
+        (&Method::GET, "/schema/input") => Ok(json_response(StatusCode::OK, &api::input_schema())),
+        (&Method::GET, "/schema/output") => Ok(json_response(StatusCode::OK, &api::output_schema())),
+        (&Method::GET, "/metrics") => Ok(text_response("text/plain; version=0.0.4", metrics(&server))),
+        _ => {
+            let error = Error::new(ErrorCode::NotFound, format!("no route for {} {}", req.method(), req.uri()));
+            Ok(error_response(&server, error))
+        },
+    }
+}
+
+fn health(server: &Server) -> Health {
+    Health {
+        status: "ok",
+        uptime_s: server.started.elapsed().as_secs(),
+        pending: server.pool.pending(),
+        capacity: server.pool.capacity(),
+    }
+}
+
+fn metrics(server: &Server) -> String {
+    let (cache_hits, cache_misses) = server.cache.counts();
+    let readings = metrics::Readings {
+        active_solves: server.pool.running(),
+        pending_requests: server.pool.pending(),
+        cache_hits,
+        cache_misses,
+    };
+    return server.metrics.render(&readings);
+}
+
+// Whether the client asked for a fresh result (which is still cached for next time)
+fn bypass_cache<T>(req: &Request<T>) -> bool {
+    req.headers().get_all(hyper::header::CACHE_CONTROL).iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.split(',').any(|directive| matches!(directive.trim(), "no-cache" | "no-store")))
+}
+
+// A result for the input from the cache, unless bypassing it
+fn cached(server: &Server, input: &api::Input, bypass: bool) -> Option<api::Output> {
+    if bypass {
+        return None;
+    }
+    let output = server.cache.get(&input.cache_key());
+    if output.is_some() {
+        tracing::info!("cache hit");
+    }
+    return output;
+}
+
+// Solve (on a worker), logging and counting the result and how long it took, and caching it
+fn solve(server: &Server, input: api::Input, observer: Option<Box<dyn Observer>>) -> Result<api::Output, Error> {
+    let key = input.cache_key();
+    let started = Instant::now();
+    let output = match observer {
+        Some(observer) => api::solve_observed(input, observer),
+        None => api::solve(input),
+    };
+    let duration = started.elapsed();
+    if let Ok(api::Output::Sudoku(output)) = &output {
+        server.metrics.solved(output.result.name(), duration);
+        tracing::info!(result = output.result.name(), duration_ms = duration.as_millis() as u64, guesses = output.stats.guesses, "solved");
+    }
+    if let Ok(output) = &output {
+        server.cache.insert(&key, output);
+    }
+    return output;
+}
+
+// Work for the pool that solves the puzzle in the current request's span
+fn solve_work(server: &Arc<Server>, input: Result<api::Input, Error>, observer: Option<Box<dyn Observer + Send>>) -> impl FnOnce() -> Result<api::Output, Error> + Send + 'static {
+    let server = server.clone();
+    let span = tracing::Span::current();
+    let timeout_ms = server.args.timeout_ms;
+    return move || {
+        let _span = span.enter();
+        let mut input = input?;
+        input.limit(timeout_ms);
+        solve(&server, input, observer.map(|observer| observer as Box<dyn Observer>))
+    };
+}
+
+async fn handle_solve_request(server: Arc<Server>, req: Request<hyper::body::Incoming>) -> Result<Response<Body>, hyper::Error> {
+    let bypass = bypass_cache(&req);
+    let input = match read_input(&server, req).await? {
+        Ok(input) => input,
+        Err(response) => return Ok(response),
+    };
+    if let Some(output) = cached(&server, &input, bypass) {
+        return Ok(json_response(StatusCode::OK, &output));
+    }
+    let ticket = match server.pool.admit() {
+        Ok(ticket) => ticket,
+        Err(error) => return Ok(error_response(&server, error)),
+    };
+    match ticket.run(solve_work(&server, Ok(input), None)).await.and_then(|output| output) {
+        Err(error) => Ok(error_response(&server, error)),
+        Ok(output) => Ok(json_response(StatusCode::OK, &output)),
+    }
+}
+
+// Server-sent events, written to the response as the solver sends them
+struct EventStream(mpsc::Receiver<Bytes>);
+
+impl hyper::body::Body for EventStream {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        self.0.poll_recv(cx).map(|event| event.map(|event| Ok(Frame::data(event))))
+    }
+}
+
+#[derive(Clone)]
+struct EventSender(mpsc::Sender<Bytes>);
+
+impl EventSender {
+
+    fn event<T: Serialize>(event: &str, data: &T) -> Bytes {
+        // Events only hold strings, numbers and the like, which always serialize
+        let data = serde_json::to_string(data).unwrap();
+        return Bytes::from(format!("event: {}\ndata: {}\n\n", event, data));
+    }
+
+    // Progress and breadcrumbs, dropped while the client is EVENT_BUFFER events behind rather than holding up
+    // the solve or queueing without limit
+    fn try_send<T: Serialize>(&self, event: &str, data: &T) {
+        let _ = self.0.try_send(Self::event(event, data));
+    }
+
+    // The final event, which waits for room
+    async fn send<T: Serialize>(&self, event: &str, data: &T) {
+        // Nobody to tell if the client has gone
+        let _ = self.0.send(Self::event(event, data)).await;
+    }
+
+}
+
+impl Observer for EventSender {
+
+    fn breadcrumb(&self, breadcrumb: String) {
+        self.try_send("breadcrumb", &breadcrumb);
+    }
+
+    fn progress(&self, reporter: &dyn Reporter, domains: &Domains, stats: &Stats) {
+        let domains = domains.iter().enumerate()
+            .map(|(id, domain)| (reporter.variable_name(id).clone(), serde_json::Value::from(domain.iter().collect::<Vec<_>>())))
+            .collect::<serde_json::Map<_, _>>();
+        self.try_send("progress", &serde_json::json!({ "domains": domains, "stats": stats }));
+    }
+
+    fn cancelled(&self) -> bool {
+        self.0.is_closed()
+    }
+
+}
+
+// Solve a puzzle, streaming server-sent events as the solver runs:
+// "progress" with the domains and stats after each round of deductions, "breadcrumb" for each deduction
+// or guess if breadcrumbs are enabled, then "output" or "error" once it's done. Progress and breadcrumbs
+// are dropped while a slow client is behind, but the last event always arrives.
+// Closing the connection cancels the solve.
+async fn handle_solve_stream_request(server: Arc<Server>, req: Request<hyper::body::Incoming>) -> Result<Response<Body>, hyper::Error> {
+    let bypass = bypass_cache(&req);
+    let input = match read_input(&server, req).await? {
+        Ok(input) => input,
+        Err(response) => return Ok(response),
+    };
+
+    let (sender, receiver) = mpsc::channel(EVENT_BUFFER);
+    let events = EventSender(sender);
+    let response = event_stream_response(receiver);
+    // A cached result comes as the only event
+    if let Some(output) = cached(&server, &input, bypass) {
+        events.send("output", &output).await;
+        return Ok(response);
+    }
+    let ticket = match server.pool.admit() {
+        Ok(ticket) => ticket,
+        Err(error) => return Ok(error_response(&server, error)),
+    };
+    let solved = ticket.run(solve_work(&server, Ok(input), Some(Box::new(events.clone()))));
+    tokio::spawn(async move {
+        match solved.await.and_then(|output| output) {
+            Ok(output) => events.send("output", &output).await,
+            Err(error) => {
+                log_error(&server, &error);
+                events.send("error", &error).await;
+            },
         }
+    }.instrument(tracing::Span::current()));
+    Ok(response)
+}
+
+fn event_stream_response(receiver: mpsc::Receiver<Bytes>) -> Response<Body> {
+    let mut response = Response::new(EventStream(receiver).boxed());
+    let headers = response.headers_mut();
+    headers.insert(hyper::header::CONTENT_TYPE, hyper::header::HeaderValue::from_static("text/event-stream"));
+    headers.insert(hyper::header::CACHE_CONTROL, hyper::header::HeaderValue::from_static("no-cache"));
+    return response;
+}
+
+// Solve an array of puzzles concurrently, responding with an array of results in the same order:
+// {"output": ...} for each puzzle solved, {"error": ...} for each that couldn't be
+// The whole batch takes one place in the queue, and its puzzles share the workers with everyone else's.
+async fn handle_solve_batch_request(server: Arc<Server>, req: Request<hyper::body::Incoming>) -> Result<Response<Body>, hyper::Error> {
+    let bypass = bypass_cache(&req);
+    let ticket = match server.pool.admit() {
+        Ok(ticket) => ticket,
+        Err(error) => return Ok(error_response(&server, error)),
+    };
+    let input_bytes = match read_body(&server, req).await? {
+        Ok(bytes) => bytes,
+        Err(response) => return Ok(response),
+    };
+    let inputs = match api::parse_batch(&input_bytes) {
+        Ok(inputs) => inputs,
+        Err(error) => return Ok(error_response(&server, error)),
+    };
+    tracing::debug!(puzzles = inputs.len(), "batch");
+
+    // Cached outputs, or solves started for the rest
+    let tasks = inputs.into_iter().map(|input| {
+        match input.as_ref().ok().and_then(|input| cached(&server, input, bypass)) {
+            Some(output) => Ok(output),
+            None => Err(ticket.run(solve_work(&server, input, None))),
+        }
+    }).collect::<Vec<_>>();
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        let result = match task {
+            Ok(output) => Ok(output),
+            Err(solving) => solving.await.and_then(|result| result),
+        };
+        if let Err(error) = &result {
+            log_error(&server, error);
+        }
+        results.push(api::BatchResult::from(result));
+    }
+    Ok(json_response(StatusCode::OK, &results))
+}
+
+// A SudokuPad link for the puzzle, without solving it
+async fn handle_share_request(server: &Server, req: Request<hyper::body::Incoming>) -> Result<Response<Body>, hyper::Error> {
+    let input = match read_input(server, req).await? {
+        Ok(input) => input,
+        Err(response) => return Ok(response),
+    };
+    match api::share(&input) {
+        Err(error) => Ok(error_response(server, error)),
+        Ok(share) => Ok(json_response(StatusCode::OK, &share)),
+    }
+}
+
+fn main() -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Solve(solve_args)) => std::process::exit(cli::run(solve_args)),
+        Some(Command::Generate(generate_args)) => std::process::exit(cli::generate(generate_args)),
+        Some(Command::Reduce(reduce_args)) => std::process::exit(cli::reduce(reduce_args)),
+        Some(Command::Canonical(canonical_args)) => std::process::exit(cli::canonical(canonical_args)),
+        None => {},
+    }
+    let args = cli.server;
+
+    let logger = tracing_subscriber::fmt().with_max_level(args.log_level.filter()).with_writer(std::io::stderr);
+    match args.log_format {
+        LogFormat::Text => logger.init(),
+        LogFormat::Json => logger.json().init(),
+    }
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(workers) = args.workers {
+        runtime.worker_threads(workers.get());
     }
+    let max_solves = args.max_solves.or_else(|| std::thread::available_parallelism().ok()).map_or(1, NonZeroUsize::get);
+    let pool = pool::Pool::new(max_solves, args.max_queued);
+    let cache = cache::Cache::new(args.cache_size);
+    let server = Server {
+        args,
+        pool,
+        cache,
+        metrics: metrics::Metrics::default(),
+        started: Instant::now(),
+        next_id: AtomicU64::new(1),
+    };
+    runtime.enable_all().build()?.block_on(serve(Arc::new(server)))
 }
 
-#[tokio::main]
-async fn main() -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000)); // TODO make the port configurable
+async fn serve(server: Arc<Server>) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let args = &server.args;
+    let addr = SocketAddr::new(args.address, args.port);
     let listener = TcpListener::bind(addr).await?;
+    tracing::info!("Listening on {}", addr);
     loop {
         let (stream, _) = listener.accept().await?;
 
@@ -95,12 +557,14 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error + Send + Sy
         // `hyper::rt` IO traits.
         let io = TokioIo::new(stream);
 
+        let server = server.clone();
         tokio::task::spawn(async move {
+            let service = hyper::service::service_fn(move |req| handle(server.clone(), req));
             if let Err(err) = http1::Builder::new()
-                .serve_connection(io, hyper::service::service_fn(handle_solve_request))
+                .serve_connection(io, service)
                 .await
             {
-                eprintln!("Error serving connection: {:?}", err);
+                tracing::error!("Error serving connection: {:?}", err);
             }
         });
     }
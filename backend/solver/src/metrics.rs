@@ -0,0 +1,121 @@
+// Counters for the server, written in the Prometheus text format for `/metrics`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+
+// Upper bounds of the latency buckets, in seconds
+const BUCKETS: [f64; 12] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default)]
+struct Histogram {
+    // Observations in each bucket (not cumulative), with one more for those above the last bound
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    solves: Mutex<BTreeMap<&'static str, u64>>,
+    errors: Mutex<BTreeMap<&'static str, u64>>,
+    latency: Mutex<Histogram>,
+}
+
+// Values kept elsewhere, read when the metrics are written
+pub struct Readings {
+    pub active_solves: usize,
+    pub pending_requests: usize,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+}
+
+impl Metrics {
+
+    // A puzzle solved, by result, and how long solving it took
+    pub fn solved(&self, result: &'static str, duration: Duration) {
+        *self.solves.lock().unwrap().entry(result).or_insert(0) += 1;
+        let seconds = duration.as_secs_f64();
+        let bucket = BUCKETS.iter().position(|bound| seconds <= *bound).unwrap_or(BUCKETS.len());
+        let mut latency = self.latency.lock().unwrap();
+        latency.counts[bucket] += 1;
+        latency.sum += seconds;
+    }
+
+    // An error returned, by code
+    pub fn error(&self, code: &'static str) {
+        *self.errors.lock().unwrap().entry(code).or_insert(0) += 1;
+    }
+
+    pub fn render(&self, readings: &Readings) -> String {
+        let mut out = String::new();
+        // Writing to a String can't fail
+        let header = |out: &mut String, name: &str, kind: &str, help: &str| {
+            writeln!(out, "# HELP {} {}", name, help).unwrap();
+            writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+        };
+
+        header(&mut out, "solver_solves_total", "counter", "Puzzles solved, by result");
+        for (result, count) in self.solves.lock().unwrap().iter() {
+            writeln!(out, "solver_solves_total{{result=\"{}\"}} {}", result, count).unwrap();
+        }
+
+        header(&mut out, "solver_solve_duration_seconds", "histogram", "Time spent solving each puzzle");
+        let latency = self.latency.lock().unwrap();
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(latency.counts.iter()) {
+            cumulative += count;
+            writeln!(out, "solver_solve_duration_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative).unwrap();
+        }
+        let total = cumulative + latency.counts[BUCKETS.len()];
+        writeln!(out, "solver_solve_duration_seconds_bucket{{le=\"+Inf\"}} {}", total).unwrap();
+        writeln!(out, "solver_solve_duration_seconds_sum {}", latency.sum).unwrap();
+        writeln!(out, "solver_solve_duration_seconds_count {}", total).unwrap();
+
+        header(&mut out, "solver_active_solves", "gauge", "Puzzles being solved now");
+        writeln!(out, "solver_active_solves {}", readings.active_solves).unwrap();
+
+        header(&mut out, "solver_pending_requests", "gauge", "Solve requests running or queued");
+        writeln!(out, "solver_pending_requests {}", readings.pending_requests).unwrap();
+
+        header(&mut out, "solver_cache_lookups_total", "counter", "Lookups in the result cache, by whether they found a result");
+        writeln!(out, "solver_cache_lookups_total{{outcome=\"hit\"}} {}", readings.cache_hits).unwrap();
+        writeln!(out, "solver_cache_lookups_total{{outcome=\"miss\"}} {}", readings.cache_misses).unwrap();
+
+        header(&mut out, "solver_errors_total", "counter", "Errors returned, by code");
+        for (code, count) in self.errors.lock().unwrap().iter() {
+            writeln!(out, "solver_errors_total{{code=\"{}\"}} {}", code, count).unwrap();
+        }
+        return out;
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.solved("solved", Duration::from_millis(3));
+        metrics.solved("solved", Duration::from_millis(30));
+        metrics.solved("timeout", Duration::from_secs(60));
+        metrics.error("invalid_puzzle");
+
+        let text = metrics.render(&Readings { active_solves: 1, pending_requests: 2, cache_hits: 3, cache_misses: 4 });
+        assert!(text.contains("\nsolver_solves_total{result=\"solved\"} 2\n"));
+        assert!(text.contains("\nsolver_solves_total{result=\"timeout\"} 1\n"));
+        assert!(text.contains("\nsolver_solve_duration_seconds_bucket{le=\"0.001\"} 0\n"));
+        assert!(text.contains("\nsolver_solve_duration_seconds_bucket{le=\"0.005\"} 1\n"));
+        assert!(text.contains("\nsolver_solve_duration_seconds_bucket{le=\"10\"} 2\n"));
+        assert!(text.contains("\nsolver_solve_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("\nsolver_solve_duration_seconds_count 3\n"));
+        assert!(text.contains("\nsolver_active_solves 1\n"));
+        assert!(text.contains("\nsolver_cache_lookups_total{outcome=\"miss\"} 4\n"));
+        assert!(text.contains("\nsolver_errors_total{code=\"invalid_puzzle\"} 1\n"));
+    }
+
+}
@@ -0,0 +1,145 @@
+// Runs solves on tokio's blocking pool, so that a hard puzzle can't hold up the threads serving connections.
+//
+// At most `workers` solves run at once. Requests admitted beyond that wait their turn, up to `queue` of
+// them; any more are turned away so that clients can back off instead of piling up.
+
+use solver::{Error, ErrorCode};
+
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+
+pub struct Pool {
+    permits: Arc<Semaphore>,
+    // Requests admitted and not yet finished, whether running or waiting
+    pending: Arc<AtomicUsize>,
+    workers: usize,
+    capacity: usize,
+}
+
+// An admitted request, which keeps its place until it and all its work are done
+pub struct Ticket {
+    permits: Arc<Semaphore>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl Pool {
+
+    pub fn new(workers: usize, queue: usize) -> Self {
+        Pool {
+            permits: Arc::new(Semaphore::new(workers.max(1))),
+            pending: Arc::new(AtomicUsize::new(0)),
+            workers: workers.max(1),
+            capacity: workers.max(1) + queue,
+        }
+    }
+
+    // Pieces of work running now
+    pub fn running(&self) -> usize {
+        self.workers - self.permits.available_permits()
+    }
+
+    // Requests admitted and not yet finished
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    // Requests that can be admitted at once
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // Admit a request, unless as many as can run or wait already are
+    pub fn admit(&self) -> Result<Arc<Ticket>, Error> {
+        let admitted = self.pending.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+            if pending < self.capacity { Some(pending + 1) } else { None }
+        });
+        if admitted.is_err() {
+            let message = format!("the solver is busy ({} requests running or queued), try again later", self.capacity);
+            return Err(Error::new(ErrorCode::Unavailable, message));
+        }
+        return Ok(Arc::new(Ticket { permits: self.permits.clone(), pending: self.pending.clone() }));
+    }
+
+}
+
+impl Ticket {
+
+    // Start CPU-bound work as soon as a worker is free. A ticket can run several pieces of work (e.g. a
+    // batch), each taking a worker of its own. The work can't be interrupted, so it carries on (and keeps
+    // its place) even if the request is dropped.
+    pub fn run<T, F>(self: &Arc<Self>, work: F) -> impl Future<Output = Result<T, Error>>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let ticket = self.clone();
+        let task = tokio::spawn(async move {
+            // The semaphore is never closed
+            let permit = ticket.permits.clone().acquire_owned().await.unwrap();
+            let result = tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                work()
+            }).await;
+            drop(ticket);
+            result
+        });
+        return async move {
+            match task.await {
+                Ok(Ok(result)) => Ok(result),
+                Ok(Err(err)) | Err(err) => Err(Error::new(ErrorCode::Internal, format!("solver failed: {}", err))),
+            }
+        };
+    }
+
+}
+
+impl Drop for Ticket {
+
+    fn drop(&mut self) {
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_admit() {
+        let pool = Pool::new(1, 1);
+        let first = pool.admit().unwrap();
+        let second = pool.admit().unwrap();
+        assert_eq!(pool.admit().err().unwrap().code, ErrorCode::Unavailable);
+
+        drop(first);
+        let third = pool.admit().unwrap();
+        assert_eq!(second.run(|| 1).await.unwrap() + third.run(|| 2).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_workers() {
+        let pool = Pool::new(2, 0);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let ticket = pool.admit().unwrap();
+        let tasks = (0..6).map(|_| {
+            let (running, peak) = (running.clone(), peak.clone());
+            ticket.run(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+        }).collect::<Vec<_>>();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+}
@@ -2,24 +2,32 @@ use crate::types::*;
 use crate::constraint::{Constraint, Constraints};
 use crate::constraints::*;
 use crate::solver::*;
+use crate::error::Error;
 
 use std::collections::HashMap;
-use std::time::Instant;
+
+pub mod text;
+pub mod fpuzzles;
+pub mod file;
+pub mod generator;
+pub mod reducer;
+pub mod symmetry;
 
 
 pub mod api {
 
-use crate::solver::{Config, SolveResult};
-use std::collections::HashMap;
+use crate::solver::{Config, SolveResult, Stats};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use serde::Deserialize;
 use serde::Serialize;
+use schemars::JsonSchema;
 
 pub type Domain = Vec<usize>;
 pub type Domains = HashMap<String, Domain>;
 pub type Cell = String;
 pub type Cells = Vec<Cell>;
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "snake_case")]
 pub enum ConstraintType {
     WhiteKropki,
@@ -32,41 +40,109 @@ pub enum ConstraintType {
     Whisper,
 }
 
-#[derive(Deserialize, Debug)]
+impl ConstraintType {
+
+    // Every type, for listing what the solver supports
+    pub const ALL: [ConstraintType; 8] = [
+        ConstraintType::WhiteKropki,
+        ConstraintType::BlackKropki,
+        ConstraintType::X,
+        ConstraintType::V,
+        ConstraintType::Thermometer,
+        ConstraintType::Palindrome,
+        ConstraintType::Renban,
+        ConstraintType::Whisper,
+    ];
+
+    // Name as it appears in the JSON
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConstraintType::WhiteKropki => "white_kropki",
+            ConstraintType::BlackKropki => "black_kropki",
+            ConstraintType::X           => "x",
+            ConstraintType::V           => "v",
+            ConstraintType::Thermometer => "thermometer",
+            ConstraintType::Palindrome  => "palindrome",
+            ConstraintType::Renban      => "renban",
+            ConstraintType::Whisper     => "whisper",
+        }
+    }
+
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Constraint {
     pub r#type: ConstraintType,
     pub cells: Cells,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct GlobalConstraints {
     pub anti_knight: bool,
     pub anti_king: bool,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
 pub struct Constraints {
     pub globals: GlobalConstraints,
     pub locals: Vec<Constraint>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug)]
+#[schemars(rename = "SudokuInput")]
 pub struct Input {
     pub domains: Domains,
     pub constraints: Constraints,
     pub config: Config,
 }
 
-#[derive(Serialize, Debug)]
+impl Input {
+
+    // The same for inputs that only differ in the order of cells, candidates or constraints, or in the
+    // limits on the solver, which can change whether it finishes but not what it finds if it does
+    pub fn canonical(&self) -> String {
+        let domains = self.domains.iter()
+            .map(|(cell, domain)| (cell, domain.iter().collect::<BTreeSet<_>>()))
+            .collect::<BTreeMap<_, _>>();
+        let mut locals = self.constraints.locals.iter().map(|local| (&local.r#type, &local.cells)).collect::<Vec<_>>();
+        locals.sort();
+        locals.dedup();
+        let config = Config { timeout_ms: None, max_nodes: None, ..self.config };
+        // Only strings, numbers and the like, which always serialize
+        return serde_json::to_string(&(domains, &self.constraints.globals, locals, config)).unwrap();
+    }
+
+}
+
+#[derive(Serialize, JsonSchema, Debug, Clone)]
+#[schemars(rename = "SudokuOutput")]
 pub struct Output {
     pub domains: Domains,
     pub result: SolveResult,
     pub duration_ms: u128,
+    pub stats: Stats,
+}
+
+// The constraints a sudoku can have
+#[derive(Serialize, Debug)]
+pub struct Capabilities {
+    pub constraint_types: Vec<ConstraintType>,
+    pub global_constraints: Vec<String>,
+}
+
+pub fn capabilities() -> Capabilities {
+    // Named as they're serialized, so the list can't fall behind the struct
+    let globals = serde_json::to_value(GlobalConstraints::default()).unwrap();
+    return Capabilities {
+        constraint_types: ConstraintType::ALL.to_vec(),
+        global_constraints: globals.as_object().unwrap().keys().cloned().collect(),
+    };
 }
 
 }
 
-struct Converter {
+// Converts a sudoku into variables (one per cell, in row-major order) and constraints for the Solver
+pub struct Converter {
     pub variable_names: Vec<String>,
     pub constraint_names: Vec<String>,
     pub domains: Domains,
@@ -76,9 +152,23 @@ struct Converter {
 
 fn grid_to_variable_id(r: usize, c: usize) -> usize { (r-1)*9 + (c-1) }
 
+fn variable_id_to_grid(id: usize) -> (usize, usize) { (id/9 + 1, id%9 + 1) }
+
+// Cells sharing an edge
+fn orthogonally_adjacent(v1: Variable, v2: Variable) -> bool {
+    let ((r1, c1), (r2, c2)) = (variable_id_to_grid(v1), variable_id_to_grid(v2));
+    return r1.abs_diff(r2) + c1.abs_diff(c2) == 1;
+}
+
+// Cells sharing an edge or a corner
+fn adjacent(v1: Variable, v2: Variable) -> bool {
+    let ((r1, c1), (r2, c2)) = (variable_id_to_grid(v1), variable_id_to_grid(v2));
+    return v1 != v2 && r1.abs_diff(r2) <= 1 && c1.abs_diff(c2) <= 1;
+}
+
 impl Converter {
 
-    pub fn new(domains: &api::Domains, constraints: &api::Constraints) -> Result<Self, String> {
+    pub fn new(domains: &api::Domains, constraints: &api::Constraints) -> Result<Self, Error> {
         let mut converter = Converter {
             variable_names: Vec::new(),
             constraint_names: Vec::new(),
@@ -108,20 +198,23 @@ impl Converter {
         self.constraints.push(constraint);
     }
 
-    fn convert_domains(&mut self, domains: &api::Domains) -> Result<(), String> {
+    fn convert_domains(&mut self, domains: &api::Domains) -> Result<(), Error> {
         if domains.len() != 9*9 {
-            return Err(format!("wrong number of cells"));
+            return Err(Error::invalid_puzzle("wrong number of cells".to_string()).with_field("domains".to_string()));
         }
         for r in 1..=9 {
             for c in 1..=9 {
                 let cell = format!("{}:{}", r, c);
                 match domains.get(&cell) {
                     None => {
-                        return Err(format!("missing domain for cell({})", cell));
+                        return Err(Error::invalid_puzzle(format!("missing domain for cell({})", cell)).with_field("domains".to_string()).with_cell(cell));
                     },
                     Some(domain) => {
                         let mut converted_domain = Domain::new();
                         for digit in domain.iter() {
+                            if !(1..=9).contains(digit) {
+                                return Err(Error::invalid_puzzle(format!("digit {} out of range 1-9", digit)).with_field(format!("domains.{}", cell)).with_cell(cell));
+                            }
                             converted_domain.insert(*digit);
                         }
                         assert_eq!(grid_to_variable_id(r, c), self.next_variable_id());
@@ -133,11 +226,11 @@ impl Converter {
         return Ok(());
     }
 
-    fn convert_cells(&self, cells: &api::Cells) -> Result<Vec<Variable>, String> {
+    fn convert_cells(&self, cells: &api::Cells) -> Result<Vec<Variable>, Error> {
         let mut variables = Vec::new();
         for cell in cells.iter() {
             match self.variable_name_to_id.get(cell) {
-                None => return Err(format!("domain missing for cell({})", cell)),
+                None => return Err(Error::invalid_puzzle(format!("domain missing for cell({})", cell)).with_cell(cell.clone())),
                 Some(id) => {
                     variables.push(*id);
                 }
@@ -147,8 +240,8 @@ impl Converter {
     }
 
     fn add_move_constraints<const N: usize>(&mut self, name: String, directions: [(isize,isize);N]) {
-        for r1 in 1..9 {
-            for c1 in 1..9 {
+        for r1 in 1..=9 {
+            for c1 in 1..=9 {
                 for (x, y) in directions {
                     let r2 = r1 + x;
                     let c2 = c1 + y;
@@ -158,18 +251,19 @@ impl Converter {
                     let mut variables = VariableSet::new();
                     variables.insert(grid_to_variable_id(r1 as usize, c1 as usize));
                     variables.insert(grid_to_variable_id(r2 as usize, c2 as usize));
+                    // Two distinct cells
                     self.add_constraint(name.clone(), Box::new(NotEquals::new(
                         self.next_constraint_id(),
                         variables,
-                    )));
+                    ).unwrap()));
                 }
             }
         }
     }
 
-    fn convert_constraints(&mut self, constraints: &api::Constraints) -> Result<(), String> {
-        for constraint in constraints.locals.iter() {
-            self.convert_constraint(constraint)?;
+    fn convert_constraints(&mut self, constraints: &api::Constraints) -> Result<(), Error> {
+        for (i, constraint) in constraints.locals.iter().enumerate() {
+            self.convert_constraint(constraint).map_err(|err| err.with_field(format!("constraints.locals[{}]", i)))?;
         }
         // TODO get rid of duplicates
         if constraints.globals.anti_knight {
@@ -184,38 +278,91 @@ impl Converter {
         return Ok(());
     }
 
-    fn convert_constraint(&mut self, constraint: &api::Constraint) -> Result<(), String> {
+    // Check the shape of a constraint's cells, so that the constraints built from them are valid
+    fn validate_cells(&self, constraint: &api::Constraint, variables: &[Variable]) -> Result<(), Error> {
+        let mut seen = VariableSet::new();
+        for variable in variables.iter() {
+            if seen.contains(*variable) {
+                let cell = &self.variable_names[*variable];
+                return Err(Error::invalid_puzzle(format!("cell({}) appears more than once", cell)).with_cell(cell.clone()));
+            }
+            seen.insert(*variable);
+        }
+        match constraint.r#type {
+            api::ConstraintType::WhiteKropki |
+            api::ConstraintType::BlackKropki |
+            api::ConstraintType::X |
+            api::ConstraintType::V => {
+                if variables.len() != 2 {
+                    return Err(Error::invalid_puzzle(format!("{} must join exactly two cells, got {}", constraint.r#type.name(), variables.len())));
+                }
+                if !orthogonally_adjacent(variables[0], variables[1]) {
+                    let cell = &self.variable_names[variables[1]];
+                    return Err(Error::invalid_puzzle(format!("{} must join cells sharing an edge", constraint.r#type.name())).with_cell(cell.clone()));
+                }
+            },
+            api::ConstraintType::Thermometer |
+            api::ConstraintType::Palindrome |
+            api::ConstraintType::Renban |
+            api::ConstraintType::Whisper => {
+                if variables.len() < 2 {
+                    return Err(Error::invalid_puzzle(format!("{} line must have at least two cells, got {}", constraint.r#type.name(), variables.len())));
+                }
+                for pair in variables.windows(2) {
+                    if !adjacent(pair[0], pair[1]) {
+                        let cell = &self.variable_names[pair[1]];
+                        return Err(Error::invalid_puzzle(format!("{} line must join neighbouring cells", constraint.r#type.name())).with_cell(cell.clone()));
+                    }
+                }
+            },
+        }
+        if matches!(constraint.r#type, api::ConstraintType::Renban) && variables.len() > 9 {
+            return Err(Error::invalid_puzzle(format!("renban line can't have more than 9 cells, got {}", variables.len())));
+        }
+        return Ok(());
+    }
+
+    fn convert_constraint(&mut self, constraint: &api::Constraint) -> Result<(), Error> {
         let id = self.next_constraint_id();
         let variable_list = self.convert_cells(&constraint.cells)?;
+        self.validate_cells(constraint, &variable_list)?;
         let variable_set = VariableSet::from_vec(&variable_list);
         match constraint.r#type {
             api::ConstraintType::WhiteKropki => {
-                self.add_constraint("white kropki".to_string(), Box::new(ConsecutiveSet::new(id, variable_set)));
+                let constraint = ConsecutiveSet::new(id, variable_set).map_err(Error::invalid_puzzle)?;
+                self.add_constraint("white kropki".to_string(), Box::new(constraint));
             },
             api::ConstraintType::BlackKropki => {
-                self.add_constraint("black kropki".to_string(), Box::new(Ratio::new(id, variable_set, 2)));
+                let constraint = Ratio::new(id, variable_set, 2).map_err(Error::invalid_puzzle)?;
+                self.add_constraint("black kropki".to_string(), Box::new(constraint));
             },
             api::ConstraintType::X => {
-                self.add_constraint("X".to_string(), Box::new(DistinctSum::new(id, variable_set, 10)));
+                let constraint = DistinctSum::new(id, variable_set, 10).map_err(Error::invalid_puzzle)?;
+                self.add_constraint("X".to_string(), Box::new(constraint));
             },
             api::ConstraintType::V => {
-                self.add_constraint("V".to_string(), Box::new(DistinctSum::new(id, variable_set, 5)));
+                let constraint = DistinctSum::new(id, variable_set, 5).map_err(Error::invalid_puzzle)?;
+                self.add_constraint("V".to_string(), Box::new(constraint));
             },
             api::ConstraintType::Thermometer => {
-                self.add_constraint("thermometer".to_string(), Box::new(Increasing::new(id, variable_list)));
+                let constraint = Increasing::new(id, variable_list).map_err(Error::invalid_puzzle)?;
+                self.add_constraint("thermometer".to_string(), Box::new(constraint));
             },
             api::ConstraintType::Palindrome => {
                 for i in 0..(variable_list.len() / 2) {
                     let id = self.next_constraint_id();
-                    let variables = vec![variable_list[i], variable_list[variable_list.len() - 1 - i]];
-                    self.add_constraint("palindrome".to_string(), Box::new(Increasing::new(id, variables)));
+                    let variables = VariableSet::from_vec(&vec![variable_list[i], variable_list[variable_list.len() - 1 - i]]);
+                    let constraint = Equals::new(id, variables).map_err(Error::invalid_puzzle)?;
+                    self.add_constraint("palindrome".to_string(), Box::new(constraint));
                 }
             },
             api::ConstraintType::Renban => {
-                self.add_constraint("renban".to_string(), Box::new(ConsecutiveSet::new(id, variable_set)));
+                let constraint = ConsecutiveSet::new(id, variable_set).map_err(Error::invalid_puzzle)?;
+                self.add_constraint("renban".to_string(), Box::new(constraint));
             },
             api::ConstraintType::Whisper => {
-                self.add_constraint("whisper".to_string(), Box::new(Difference::new(id, variable_list, 5)));
+                let constraint = Difference::new(id, variable_list, 5).map_err(Error::invalid_puzzle)?;
+                self.add_constraint("whisper".to_string(), Box::new(constraint));
             },
         }
         return Ok(());
@@ -231,7 +378,7 @@ impl Converter {
             let id = self.next_constraint_id();
             self.add_constraint(
                 format!("sudoku row({})", r),
-                Box::new(Permutation::new(id, variables, domain))
+                Box::new(Permutation::new(id, variables, domain).unwrap())
             );
         }
         for c in 1..=9 {
@@ -242,7 +389,7 @@ impl Converter {
             let id = self.constraint_names.len();
             self.add_constraint(
                 format!("sudoku col({})", c),
-                Box::new(Permutation::new(id, variables, domain))
+                Box::new(Permutation::new(id, variables, domain).unwrap())
             );
         }
         for box_x in 0..3 {
@@ -258,7 +405,7 @@ impl Converter {
                 let id = self.constraint_names.len();
                 self.add_constraint(
                     format!("sudoku box({})", box_x*3 + box_y + 1),
-                    Box::new(Permutation::new(id, variables, domain))
+                    Box::new(Permutation::new(id, variables, domain).unwrap())
                 );
             }
         }
@@ -266,7 +413,28 @@ impl Converter {
 
 }
 
-pub fn solve(input: api::Input) -> Result<api::Output, String> {
+// Count the solutions of a sudoku, up to `limit`, searching exhaustively
+pub fn count_solutions(domains: &api::Domains, constraints: &api::Constraints, config: Config, limit: usize) -> Result<(Solutions, Stats), Error> {
+    let converter = Converter::new(domains, constraints)?;
+
+    let mut domains = converter.domains;
+    let mut constraints = converter.constraints;
+
+    let solver = Solver{
+        variable_names: converter.variable_names,
+        constraint_names: converter.constraint_names,
+        config,
+        observer: None,
+    };
+    return Ok(solver.count_solutions(&mut domains, &mut constraints, limit));
+}
+
+pub fn solve(input: api::Input) -> Result<api::Output, Error> {
+    return solve_observed(input, None);
+}
+
+// Solve, telling the observer (if any) about progress and breadcrumbs as they happen
+pub fn solve_observed(input: api::Input, observer: Option<Box<dyn Observer>>) -> Result<api::Output, Error> {
 
     let converter = Converter::new(&input.domains, &input.constraints)?;
 
@@ -277,10 +445,11 @@ pub fn solve(input: api::Input) -> Result<api::Output, String> {
         variable_names: converter.variable_names,
         constraint_names: converter.constraint_names,
         config: input.config,
+        observer,
     };
 
     let now = Instant::now();
-    let result = solver.solve(&mut domains, &mut constraints);
+    let (result, stats) = solver.solve(&mut domains, &mut constraints);
     let elapsed = now.elapsed();
 
     let mut output_domains = api::Domains::new();
@@ -291,8 +460,9 @@ pub fn solve(input: api::Input) -> Result<api::Output, String> {
 
     return Ok(api::Output{
         domains: output_domains,
-        result: result,
+        result,
         duration_ms: elapsed.as_millis(),
+        stats,
     });
 }
 
@@ -300,6 +470,7 @@ pub fn solve(input: api::Input) -> Result<api::Output, String> {
 mod tests {
 
     use super::*;
+    use crate::branching::*;
 
     fn convert_grid(grid: [[usize; 9]; 9]) -> api::Domains {
         let mut domains = api::Domains::new();
@@ -322,6 +493,7 @@ mod tests {
         let config = Config{
             breadcrumbs: false,
             greedy: false,
+            ..Config::default()
         };
 
         let domains = convert_grid([
@@ -374,4 +546,349 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_stats() {
+        // test_simple_sudoku, solved by the rows, columns and boxes alone
+        let domains = text::parse("...1.2....6.....7...8...9..4.......3.5...7...2...8...1..9...8.5.7.....6....3.4...").unwrap();
+        let output = solve(api::Input { domains, constraints: api::Constraints::default(), config: Config::default() }).unwrap();
+        assert!(matches!(output.result, SolveResult::Solved));
+        assert!(output.stats.peak_constraints >= 27);
+        assert!(output.stats.invocations["permutation"] > 0);
+        assert!(!output.stats.invocations.contains_key("equals"));
+    }
+
+    #[test]
+    fn test_sudoku_requiring_guesses() {
+
+        let config = Config{
+            breadcrumbs: false,
+            greedy: false,
+            ..Config::default()
+        };
+
+        let domains = convert_grid([
+            [4, 0, 0, 0, 0, 0, 8, 0, 5],
+            [0, 3, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 7, 0, 0, 0, 0, 0],
+            [0, 2, 0, 0, 0, 0, 0, 6, 0],
+            [0, 0, 0, 0, 8, 0, 4, 0, 0],
+            [0, 0, 0, 0, 1, 0, 0, 0, 0],
+            [0, 0, 0, 6, 0, 3, 0, 7, 0],
+            [5, 0, 0, 2, 0, 0, 0, 0, 0],
+            [1, 0, 4, 0, 0, 0, 0, 0, 0],
+        ]);
+
+        let input = api::Input {
+            domains,
+            constraints: api::Constraints {
+                globals: api::GlobalConstraints {
+                    anti_knight: false,
+                    anti_king: false,
+                },
+                locals: Vec::new(),
+            },
+            config,
+        };
+
+        let expected_domains = convert_grid([
+            [4, 1, 7, 3, 6, 9, 8, 2, 5],
+            [6, 3, 2, 1, 5, 8, 9, 4, 7],
+            [9, 5, 8, 7, 2, 4, 3, 1, 6],
+            [8, 2, 5, 4, 3, 7, 1, 6, 9],
+            [7, 9, 1, 5, 8, 6, 4, 3, 2],
+            [3, 4, 6, 9, 1, 2, 7, 5, 8],
+            [2, 8, 9, 6, 4, 3, 5, 7, 1],
+            [5, 7, 3, 2, 9, 1, 6, 8, 4],
+            [1, 6, 4, 8, 7, 5, 2, 9, 3],
+        ]);
+
+        let output = solve(input);
+
+        match output {
+            Ok(output) => {
+                assert!(matches!(output.result, SolveResult::Solved));
+                assert_eq!(output.domains, expected_domains);
+            },
+            Err(err) => {
+                panic!("{}", err);
+            }
+        }
+
+    }
+
+    #[test]
+    fn test_branching_strategies() {
+
+        let grid = [
+            [4, 0, 0, 0, 0, 0, 8, 0, 5],
+            [0, 3, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 7, 0, 0, 0, 0, 0],
+            [0, 2, 0, 0, 0, 0, 0, 6, 0],
+            [0, 0, 0, 0, 8, 0, 4, 0, 0],
+            [0, 0, 0, 0, 1, 0, 0, 0, 0],
+            [0, 0, 0, 6, 0, 3, 0, 7, 0],
+            [5, 0, 0, 2, 0, 0, 0, 0, 0],
+            [1, 0, 4, 0, 0, 0, 0, 0, 0],
+        ];
+
+        let branchings = [Branching::Mrv, Branching::Degree, Branching::DomWdeg, Branching::Impact, Branching::Random];
+        let value_orderings = [ValueOrdering::Ascending, ValueOrdering::Descending, ValueOrdering::Random];
+
+        for branching in branchings {
+            for value_ordering in value_orderings {
+                let config = Config{
+                    breadcrumbs: false,
+                    greedy: true,
+                    branching,
+                    value_ordering,
+                    seed: 42,
+                    ..Config::default()
+                };
+
+                let input = api::Input {
+                    domains: convert_grid(grid),
+                    constraints: api::Constraints {
+                        globals: api::GlobalConstraints {
+                            anti_knight: false,
+                            anti_king: false,
+                        },
+                        locals: Vec::new(),
+                    },
+                    config,
+                };
+
+                let output = solve(input).unwrap();
+                assert!(matches!(output.result, SolveResult::Solved), "{:?} {:?}", branching, value_ordering);
+            }
+        }
+
+    }
+
+    #[test]
+    fn test_node_limit() {
+
+        let config = Config{
+            breadcrumbs: false,
+            greedy: false,
+            max_nodes: Some(1),
+            ..Config::default()
+        };
+
+        let domains = convert_grid([
+            [4, 8, 0, 3, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 7, 1],
+            [0, 2, 0, 0, 0, 0, 0, 0, 0],
+            [7, 0, 5, 0, 0, 0, 0, 6, 0],
+            [0, 0, 0, 2, 0, 0, 8, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 1, 0, 7, 6, 0, 0, 0],
+            [3, 0, 0, 0, 0, 0, 4, 0, 0],
+            [0, 0, 0, 0, 5, 0, 0, 0, 0],
+        ]);
+
+        let input = api::Input {
+            domains,
+            constraints: api::Constraints {
+                globals: api::GlobalConstraints {
+                    anti_knight: false,
+                    anti_king: false,
+                },
+                locals: Vec::new(),
+            },
+            config,
+        };
+
+        let solution = convert_grid([
+            [4, 8, 7, 3, 1, 2, 6, 9, 5],
+            [5, 9, 3, 6, 8, 4, 2, 7, 1],
+            [1, 2, 6, 5, 9, 7, 3, 8, 4],
+            [7, 3, 5, 8, 4, 9, 1, 6, 2],
+            [9, 1, 4, 2, 6, 5, 8, 3, 7],
+            [2, 6, 8, 7, 3, 1, 5, 4, 9],
+            [8, 5, 1, 4, 7, 6, 9, 2, 3],
+            [3, 7, 9, 1, 2, 8, 4, 5, 6],
+            [6, 4, 2, 9, 5, 3, 7, 1, 8],
+        ]);
+
+        let output = solve(input).unwrap();
+
+        assert!(matches!(output.result, SolveResult::Timeout));
+        assert_eq!(output.stats.guesses, 1);
+        assert_eq!(output.stats.max_depth, 1);
+        assert_eq!(output.stats.guessing_rounds, 0);
+
+        // The guess must have been undone, leaving only sound deductions
+        for (cell, domain) in solution.iter() {
+            assert!(output.domains[cell].contains(&domain[0]), "{}", cell);
+        }
+
+    }
+
+    #[test]
+    fn test_timeout() {
+
+        let config = Config{
+            breadcrumbs: false,
+            greedy: false,
+            timeout_ms: Some(0),
+            ..Config::default()
+        };
+
+        let input = api::Input {
+            domains: text::parse("48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....").unwrap(),
+            constraints: api::Constraints::default(),
+            config,
+        };
+
+        let output = solve(input).unwrap();
+
+        // The deadline has passed by the first guess, so only deductions are kept
+        assert!(matches!(output.result, SolveResult::Timeout));
+        assert_eq!(output.stats.guesses, 0);
+        let solution = text::parse("487312695593684271126597384735849162914265837268731549851476923379128456642953718").unwrap();
+        for (cell, domain) in solution.iter() {
+            assert!(output.domains[cell].contains(&domain[0]), "{}", cell);
+        }
+        assert!(output.domains.values().any(|domain| domain.len() > 1));
+
+    }
+
+    #[test]
+    fn test_invalid_puzzles() {
+
+        let empty_grid = [[0; 9]; 9];
+
+        let solve_with = |domains: api::Domains, locals: Vec<(api::ConstraintType, Vec<&str>)>| {
+            let input = api::Input {
+                domains,
+                constraints: api::Constraints {
+                    globals: api::GlobalConstraints {
+                        anti_knight: false,
+                        anti_king: false,
+                    },
+                    locals: locals.into_iter().map(|(r#type, cells)| api::Constraint {
+                        r#type,
+                        cells: cells.into_iter().map(|cell| cell.to_string()).collect(),
+                    }).collect(),
+                },
+                // Only the conversion matters here, so don't search
+                config: Config { max_nodes: Some(0), ..Config::default() },
+            };
+            solve(input)
+        };
+
+        let mut domains = convert_grid(empty_grid);
+        domains.insert("5:5".to_string(), vec![10]);
+        let err = solve_with(domains, Vec::new()).unwrap_err();
+        assert_eq!(err.cell, Some("5:5".to_string()));
+
+        let err = solve_with(convert_grid(empty_grid), vec![(api::ConstraintType::Renban, vec!["1:1"])]).unwrap_err();
+        assert_eq!(err.field, Some("constraints.locals[0]".to_string()));
+
+        let err = solve_with(convert_grid(empty_grid), vec![(api::ConstraintType::Thermometer, vec!["1:1", "1:2", "1:1"])]).unwrap_err();
+        assert_eq!(err.cell, Some("1:1".to_string()));
+
+        let err = solve_with(convert_grid(empty_grid), vec![(api::ConstraintType::BlackKropki, vec!["1:1", "2:2"])]).unwrap_err();
+        assert_eq!(err.cell, Some("2:2".to_string()));
+
+        let err = solve_with(convert_grid(empty_grid), vec![(api::ConstraintType::Whisper, vec!["1:1", "1:3"])]).unwrap_err();
+        assert_eq!(err.cell, Some("1:3".to_string()));
+
+        assert!(solve_with(convert_grid(empty_grid), vec![(api::ConstraintType::Whisper, vec!["1:1", "2:2"])]).is_ok());
+
+    }
+
+    #[test]
+    fn test_count_solutions() {
+        let domains = text::parse("48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....").unwrap();
+        let constraints = api::Constraints::default();
+
+        let (solutions, stats) = count_solutions(&domains, &constraints, Config::default(), 2).unwrap();
+        assert_eq!(solutions.count, 1);
+        assert!(!solutions.timeout);
+        assert!(stats.guesses > 0);
+        assert!(stats.max_depth > 1);
+        assert_eq!(stats.guessing_rounds, 0);
+        let first = solutions.first.unwrap().iter().map(|domain| domain.iter().next().unwrap().to_string()).collect::<String>();
+        assert_eq!(first, "487312695593684271126597384735849162914265837268731549851476923379128456642953718");
+
+        let mut domains = domains;
+        domains.insert("1:1".to_string(), (1..=9).collect());
+        domains.insert("1:2".to_string(), (1..=9).collect());
+        let (solutions, _) = count_solutions(&domains, &constraints, Config::default(), 2).unwrap();
+        assert_eq!(solutions.count, 2);
+
+        let config = Config { max_nodes: Some(1), ..Config::default() };
+        let (solutions, _) = count_solutions(&domains, &constraints, config, 2).unwrap();
+        assert!(solutions.timeout);
+    }
+
+    // Records what it's told, cancelling after the first round of deductions if asked to
+    struct Recorder {
+        events: std::rc::Rc<std::cell::RefCell<Vec<String>>>,
+        cancel: bool,
+    }
+
+    impl Observer for Recorder {
+        fn breadcrumb(&self, breadcrumb: String) {
+            self.events.borrow_mut().push(breadcrumb);
+        }
+        fn progress(&self, reporter: &dyn Reporter, domains: &Domains, stats: &Stats) {
+            let solved = (0..domains.len()).filter(|id| domains[*id].len() == 1).count();
+            self.events.borrow_mut().push(format!("progress {} {} {}", reporter.variable_name(0), solved, stats.guesses));
+        }
+        fn cancelled(&self) -> bool {
+            self.cancel && !self.events.borrow().is_empty()
+        }
+    }
+
+    #[test]
+    fn test_solve_observed() {
+        let domains = text::parse("48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....").unwrap();
+        let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let input = api::Input { domains: domains.clone(), constraints: api::Constraints::default(), config: Config { breadcrumbs: true, ..Config::default() } };
+        let output = solve_observed(input, Some(Box::new(Recorder { events: events.clone(), cancel: false }))).unwrap();
+        assert!(matches!(output.result, SolveResult::Solved));
+        let events = events.borrow();
+        assert!(events[0].ends_with(" by sudoku row(1)"));
+        let progress = events.iter().filter(|event| event.starts_with("progress 1:1 ")).collect::<Vec<_>>();
+        assert!(progress.len() > 1);
+        assert!(progress[0].ends_with(" 0"));
+        assert!(events.iter().any(|event| event.starts_with("guess ")));
+
+        // Cancelling keeps the deductions made before any guessing
+        let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let input = api::Input { domains, constraints: api::Constraints::default(), config: Config::default() };
+        let output = solve_observed(input, Some(Box::new(Recorder { events: events.clone(), cancel: true }))).unwrap();
+        assert!(matches!(output.result, SolveResult::Timeout));
+        assert_eq!(output.stats.guesses, 0);
+        assert_eq!(events.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_palindrome() {
+        // test_simple_sudoku, where 1:3 and 2:5 are both 4 (and a thermometer-like reading of the line would fail)
+        let domains = text::parse("...1.2....6.....7...8...9..4.......3.5...7...2...8...1..9...8.5.7.....6....3.4...").unwrap();
+        let palindrome = api::Constraint {
+            r#type: api::ConstraintType::Palindrome,
+            cells: vec!["1:3".to_string(), "1:4".to_string(), "2:5".to_string()],
+        };
+        let constraints = api::Constraints { locals: vec![palindrome], ..api::Constraints::default() };
+        let output = solve(api::Input { domains, constraints, config: Config::default() }).unwrap();
+        assert!(matches!(output.result, SolveResult::Solved));
+        assert_eq!(output.domains["1:3"], output.domains["2:5"]);
+    }
+
+    #[test]
+    fn test_move_constraints() {
+        // Every pair of cells a king's move apart, in both directions, including along the last row and column
+        let globals = api::GlobalConstraints { anti_knight: false, anti_king: true };
+        let converter = Converter::new(&convert_grid([[0; 9]; 9]), &api::Constraints { globals, locals: Vec::new() }).unwrap();
+        assert_eq!(converter.constraints.len(), 27 + 2 * (2 * 9 * 8 + 2 * 8 * 8));
+
+        let globals = api::GlobalConstraints { anti_knight: true, anti_king: false };
+        let converter = Converter::new(&convert_grid([[0; 9]; 9]), &api::Constraints { globals, locals: Vec::new() }).unwrap();
+        assert_eq!(converter.constraints.len(), 27 + 2 * (4 * 7 * 8));
+    }
+
 }
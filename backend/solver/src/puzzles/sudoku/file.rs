@@ -0,0 +1,186 @@
+// Puzzle files: a sudoku and its constraints, without any solver configuration, e.g.
+//
+//   {
+//     "version": 1,
+//     "title": "kropki",
+//     "domains": {
+//       "1:1": [1,2,3,4,5,6,7,8,9],
+//       ...
+//     },
+//     "constraints": {
+//       "globals": {"anti_knight":false,"anti_king":false},
+//       "locals": [
+//         {"type":"white_kropki","cells":["1:1","1:2"]},
+//         ...
+//       ]
+//     }
+//   }
+//
+// The domains and constraints are as in `api::Input`. Every file records the version of the format it
+// was written in, so that the format can change without breaking old files; files from a newer version
+// than this one are rejected rather than misread.
+
+use super::{api, Converter};
+use crate::api::parse_value;
+use crate::error::{Error, ErrorCode};
+use crate::solver::Config;
+
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+
+pub const VERSION: u64 = 1;
+
+// Lines longer than this are split over several
+const WIDTH: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PuzzleFile {
+    pub version: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(serialize_with = "sorted")]
+    pub domains: api::Domains,
+    #[serde(default)]
+    pub constraints: api::Constraints,
+}
+
+// Cells in row-major order, so that files diff well
+pub(crate) fn sorted<S: Serializer>(domains: &api::Domains, serializer: S) -> Result<S::Ok, S::Error> {
+    domains.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+impl PuzzleFile {
+
+    pub fn new(input: api::Input, title: Option<String>) -> Self {
+        PuzzleFile {
+            version: VERSION,
+            title,
+            domains: input.domains,
+            constraints: input.constraints,
+        }
+    }
+
+    pub fn into_input(self, config: Config) -> api::Input {
+        api::Input {
+            domains: self.domains,
+            constraints: self.constraints,
+            config,
+        }
+    }
+
+}
+
+// Parse and validate a puzzle file
+pub fn read(text: &str) -> Result<PuzzleFile, Error> {
+    let value : Value = serde_json::from_str(text).map_err(|err| Error::new(ErrorCode::MalformedInput, err.to_string()))?;
+    return read_value(value);
+}
+
+pub fn read_value(value: Value) -> Result<PuzzleFile, Error> {
+    match value.get("version").map(Value::as_u64) {
+        Some(Some(version)) if (1..=VERSION).contains(&version) => {},
+        Some(Some(version)) => {
+            let message = format!("puzzle file version {} isn't supported (latest is {})", version, VERSION);
+            return Err(Error::new(ErrorCode::Unsupported, message).with_field("version".to_string()));
+        },
+        Some(None) => return Err(Error::new(ErrorCode::MalformedInput, "version must be a number".to_string()).with_field("version".to_string())),
+        None => return Err(Error::new(ErrorCode::MalformedInput, "missing field `version`".to_string())),
+    }
+    let file : PuzzleFile = parse_value(value)?;
+    Converter::new(&file.domains, &file.constraints)?;
+    return Ok(file);
+}
+
+// Write a container on one line if it fits after `column` characters, otherwise one item per line
+fn write_value(value: &Value, depth: usize, column: usize, out: &mut String) {
+    let compact = value.to_string();
+    let items : Vec<(Option<String>, &Value)> = match value {
+        _ if column + compact.len() <= WIDTH => vec![],
+        Value::Array(items) => items.iter().map(|item| (None, item)).collect(),
+        Value::Object(fields) => fields.iter().map(|(key, item)| (Some(Value::from(key.as_str()).to_string()), item)).collect(),
+        _ => vec![],
+    };
+    if items.is_empty() {
+        out.push_str(&compact);
+        return;
+    }
+    let indent = "  ".repeat(depth + 1);
+    let (open, close) = if value.is_array() { ('[', ']') } else { ('{', '}') };
+    out.push(open);
+    for (i, (key, item)) in items.iter().enumerate() {
+        out.push('\n');
+        out.push_str(&indent);
+        let mut column = indent.len();
+        if let Some(key) = key {
+            out.push_str(key);
+            out.push_str(": ");
+            column += key.len() + 2;
+        }
+        write_value(item, depth + 1, column, out);
+        if i + 1 < items.len() {
+            out.push(',');
+        }
+    }
+    out.push('\n');
+    out.push_str(&indent[2..]);
+    out.push(close);
+}
+
+pub fn write(file: &PuzzleFile) -> String {
+    // Puzzle files only hold strings, numbers and the like, which always serialize
+    let value = serde_json::to_value(file).unwrap();
+    let mut out = String::new();
+    write_value(&value, 0, 0, &mut out);
+    out.push('\n');
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::puzzles::sudoku::text;
+
+    const LINE : &str = "48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....";
+
+    fn puzzle() -> PuzzleFile {
+        let constraints = serde_json::from_value(serde_json::json!({
+            "globals": { "anti_knight": true, "anti_king": false },
+            "locals": [
+                { "type": "white_kropki", "cells": ["1:3", "1:4"] },
+                { "type": "thermometer", "cells": ["2:1", "2:2", "2:3"] },
+            ],
+        })).unwrap();
+        let input = api::Input { domains: text::parse(LINE).unwrap(), constraints, config: Config::default() };
+        return PuzzleFile::new(input, Some("test".to_string()));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let written = write(&puzzle());
+        assert!(written.starts_with("{\n  \"version\": 1,\n  \"title\": \"test\",\n  \"domains\": {\n    \"1:1\": [4],\n"));
+        assert!(written.contains("\n      {\"type\":\"white_kropki\",\"cells\":[\"1:3\",\"1:4\"]},\n"));
+
+        let file = read(&written).unwrap();
+        assert_eq!(file.title, Some("test".to_string()));
+        assert_eq!(file.domains, puzzle().domains);
+        assert!(file.constraints.globals.anti_knight);
+        assert_eq!(write(&file), written);
+    }
+
+    #[test]
+    fn test_versions() {
+        let mut value = serde_json::to_value(puzzle()).unwrap();
+        value["version"] = Value::from(VERSION + 1);
+        assert_eq!(read_value(value.clone()).unwrap_err().code, ErrorCode::Unsupported);
+        value.as_object_mut().unwrap().remove("version");
+        assert_eq!(read_value(value.clone()).unwrap_err().code, ErrorCode::MalformedInput);
+
+        value["version"] = Value::from(1);
+        value["domains"].as_object_mut().unwrap().remove("9:9");
+        assert_eq!(read_value(value).unwrap_err().code, ErrorCode::InvalidPuzzle);
+    }
+
+}
@@ -0,0 +1,502 @@
+// f-puzzles JSON, as used by f-puzzles.com, SudokuPad and the Cracking the Cryptic app.
+//
+// Puzzles are shared as LZ-string compressed, base64 encoded JSON, usually in a link such as
+//
+//   https://sudokupad.app/fpuzzles<data>
+//   https://app.crackingthecryptic.com/sudoku/?puzzleid=fpuzzles<data>
+//   https://www.f-puzzles.com/?load=<data>
+//
+// Cells are named "R<row>C<column>", counting from 1.
+
+use super::api;
+use crate::error::{Error, ErrorCode};
+use crate::solver::Config;
+
+use serde_json::{json, Map, Value};
+
+
+// Where exported puzzles are opened
+pub const URL_PREFIX: &str = "https://sudokupad.app/fpuzzles";
+
+// A puzzle read from f-puzzles data, along with the features it uses that the solver can't handle.
+// The input is solvable as is, but the solution may not respect the unsupported features.
+#[derive(Debug)]
+pub struct Import {
+    pub input: api::Input,
+    pub unsupported: Vec<String>,
+}
+
+impl Import {
+
+    // The input, or an error naming the unsupported features if there are any
+    pub fn strict(self) -> Result<api::Input, Error> {
+        if !self.unsupported.is_empty() {
+            let message = format!("unsupported f-puzzles features: {}", self.unsupported.join(", "));
+            return Err(Error::new(ErrorCode::Unsupported, message));
+        }
+        return Ok(self.input);
+    }
+
+}
+
+// Keys that only affect how a puzzle looks, or that describe the solver's progress rather than the rules
+const COSMETIC: &[&str] = &[
+    "size", "title", "author", "ruleset", "solution", "grid",
+    "line", "text", "circle", "rectangle", "cage",
+    "highlightConflicts", "disabledlogic", "truecandidatesoptions",
+];
+
+// Keys of a grid cell that don't affect the rules
+const COSMETIC_CELL: &[&str] = &[
+    "value", "given", "givenPencilMarks", "centerPencilMarks", "cornerPencilMarks", "highlight", "c",
+];
+
+fn malformed(message: String) -> Error {
+    Error::new(ErrorCode::MalformedInput, message)
+}
+
+// Whether a value turns a feature on: true, or a non-empty list or object
+fn enabled(value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => *b,
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+        _ => false,
+    }
+}
+
+// Decode the JSON from a link, compressed data, or the JSON itself
+pub fn decode(data: &str) -> Result<String, Error> {
+    let data = data.trim();
+    if data.starts_with('{') {
+        return Ok(data.to_string());
+    }
+
+    let data = if let Some((_, rest)) = data.split_once("fpuzzles") {
+        rest
+    } else if let Some((_, rest)) = data.split_once("load=") {
+        rest
+    } else if data.contains("://") {
+        return Err(Error::new(ErrorCode::Unsupported, "only f-puzzles links are supported".to_string()));
+    } else {
+        data
+    };
+    let data = data.split(['&', '#']).next().unwrap();
+    let data = data.replace("%2B", "+").replace("%2F", "/").replace("%3D", "=").replace(' ', "+");
+
+    let decompressed = lz_str::decompress_from_base64(&data)
+        .ok_or_else(|| malformed("f-puzzles data isn't valid LZ-string compressed base64".to_string()))?;
+    return String::from_utf16(&decompressed)
+        .map_err(|_| malformed("f-puzzles data doesn't decompress to text".to_string()));
+}
+
+// Parse f-puzzles data in any form accepted by `decode`
+pub fn import(data: &str) -> Result<Import, Error> {
+    let json = decode(data)?;
+    let value : Value = serde_json::from_str(&json).map_err(|err| malformed(err.to_string()))?;
+    let fields = match value {
+        Value::Object(fields) => fields,
+        _ => return Err(malformed("f-puzzles data must be an object".to_string())),
+    };
+    return Importer::default().import(&fields);
+}
+
+// "3:4" to "R3C4"
+fn export_cell(name: &str, field: &str) -> Result<String, Error> {
+    let parsed = name.split_once(':').and_then(|(r, c)| {
+        let (r, c) = (r.parse::<usize>().ok()?, c.parse::<usize>().ok()?);
+        return if (1..=9).contains(&r) && (1..=9).contains(&c) { Some(format!("R{}C{}", r, c)) } else { None };
+    });
+    return parsed.ok_or_else(|| Error::invalid_puzzle(format!("invalid cell {}", name)).with_field(field.to_string()));
+}
+
+// f-puzzles JSON for a sudoku, with single candidates as givens and other restricted cells as given pencil marks
+pub fn export(input: &api::Input) -> Result<Value, Error> {
+    let mut grid = vec![vec![json!({}); 9]; 9];
+    for (name, domain) in input.domains.iter() {
+        let field = format!("domains.{}", name);
+        let cell = export_cell(name, &field)?;
+        let (r, c) = position(&cell);
+        if let Some(digit) = domain.iter().find(|d| !(1..=9).contains(*d)) {
+            return Err(Error::invalid_puzzle(format!("invalid digit {}", digit)).with_field(field).with_cell(name.clone()));
+        }
+        let mut domain = domain.clone();
+        domain.sort();
+        domain.dedup();
+        grid[r][c] = match domain.len() {
+            // A blank cell would make a contradiction solvable
+            0 => return Err(Error::invalid_puzzle(format!("no candidates for cell({})", name)).with_field(field).with_cell(name.clone())),
+            1 => json!({ "value": domain[0], "given": true }),
+            2..=8 => json!({ "givenPencilMarks": domain }),
+            _ => json!({}),
+        };
+    }
+
+    let mut fields = Map::new();
+    fields.insert("size".to_string(), json!(9));
+    fields.insert("grid".to_string(), json!(grid));
+    if input.constraints.globals.anti_knight {
+        fields.insert("antiknight".to_string(), json!(true));
+    }
+    if input.constraints.globals.anti_king {
+        fields.insert("antiking".to_string(), json!(true));
+    }
+
+    for (i, constraint) in input.constraints.locals.iter().enumerate() {
+        let field = format!("constraints.locals[{}]", i);
+        let cells = constraint.cells.iter().map(|cell| export_cell(cell, &field)).collect::<Result<Vec<_>, _>>()?;
+        let (key, entry) = match constraint.r#type {
+            api::ConstraintType::Thermometer => ("thermometer", json!({ "lines": [cells] })),
+            api::ConstraintType::Palindrome  => ("palindrome", json!({ "lines": [cells] })),
+            api::ConstraintType::Renban      => ("renban", json!({ "lines": [cells] })),
+            api::ConstraintType::Whisper     => ("whispers", json!({ "lines": [cells] })),
+            api::ConstraintType::WhiteKropki => ("difference", json!({ "cells": cells })),
+            api::ConstraintType::BlackKropki => ("ratio", json!({ "cells": cells })),
+            api::ConstraintType::X           => ("xv", json!({ "cells": cells, "value": "X" })),
+            api::ConstraintType::V           => ("xv", json!({ "cells": cells, "value": "V" })),
+        };
+        match fields.entry(key).or_insert_with(|| json!([])) {
+            Value::Array(entries) => entries.push(entry),
+            _ => unreachable!(),
+        }
+    }
+
+    return Ok(Value::Object(fields));
+}
+
+// LZ-string compressed, base64 encoded JSON, as used in links
+pub fn compress(json: &Value) -> String {
+    lz_str::compress_to_base64(json.to_string().as_str())
+}
+
+// A link that opens the puzzle in SudokuPad
+pub fn to_url(input: &api::Input) -> Result<String, Error> {
+    return Ok(format!("{}{}", URL_PREFIX, compress(&export(input)?)));
+}
+
+// Row and column, counting from 0, of a cell named "R3C4"
+fn position(cell: &str) -> (usize, usize) {
+    let (r, c) = cell[1..].split_once('C').unwrap();
+    return (r.parse::<usize>().unwrap() - 1, c.parse::<usize>().unwrap() - 1);
+}
+
+// "R3C4" to "3:4"
+fn cell(name: &Value, field: &str) -> Result<String, Error> {
+    let parsed = name.as_str().and_then(|name| {
+        let (r, c) = name.strip_prefix('R')?.split_once('C')?;
+        let (r, c) = (r.parse::<usize>().ok()?, c.parse::<usize>().ok()?);
+        return if (1..=9).contains(&r) && (1..=9).contains(&c) { Some(format!("{}:{}", r, c)) } else { None };
+    });
+    return parsed.ok_or_else(|| Error::invalid_puzzle(format!("invalid cell {}", name)).with_field(field.to_string()));
+}
+
+fn cells(names: &Value, field: &str) -> Result<api::Cells, Error> {
+    let names = names.as_array().ok_or_else(|| malformed("expected a list of cells".to_string()).with_field(field.to_string()))?;
+    return names.iter().map(|name| cell(name, field)).collect();
+}
+
+fn entries<'a>(value: &'a Value, key: &str) -> Result<&'a Vec<Value>, Error> {
+    return value.as_array().ok_or_else(|| malformed(format!("expected a list of {}", key)).with_field(key.to_string()));
+}
+
+#[derive(Default)]
+struct Importer {
+    constraints: api::Constraints,
+    unsupported: Vec<String>,
+}
+
+impl Importer {
+
+    fn unsupported(&mut self, feature: String) {
+        if !self.unsupported.contains(&feature) {
+            self.unsupported.push(feature);
+        }
+    }
+
+    fn import(mut self, fields: &Map<String, Value>) -> Result<Import, Error> {
+        match fields.get("size").and_then(Value::as_u64) {
+            Some(9) => {},
+            Some(size) => return Err(Error::new(ErrorCode::Unsupported, format!("unsupported grid size {}", size)).with_field("size".to_string())),
+            None => return Err(malformed("missing grid size".to_string()).with_field("size".to_string())),
+        }
+
+        let domains = self.domains(fields.get("grid"))?;
+
+        for (key, value) in fields.iter() {
+            match key.as_str() {
+                "thermometer" => self.lines(key, value, api::ConstraintType::Thermometer)?,
+                "palindrome"  => self.lines(key, value, api::ConstraintType::Palindrome)?,
+                "renban"      => self.lines(key, value, api::ConstraintType::Renban)?,
+                "whispers"    => self.lines(key, value, api::ConstraintType::Whisper)?,
+                "difference"  => self.dots(key, value, |v| (v.unwrap_or("1") == "1").then_some(api::ConstraintType::WhiteKropki))?,
+                "ratio"       => self.dots(key, value, |v| (v.unwrap_or("2") == "2").then_some(api::ConstraintType::BlackKropki))?,
+                "xv"          => self.dots(key, value, |v| match v {
+                    Some("X") | Some("x") => Some(api::ConstraintType::X),
+                    Some("V") | Some("v") => Some(api::ConstraintType::V),
+                    _ => None,
+                })?,
+                "antiknight"  => self.constraints.globals.anti_knight |= value.as_bool().unwrap_or(false),
+                "antiking"    => self.constraints.globals.anti_king |= value.as_bool().unwrap_or(false),
+                // Written by older versions of the Python frontend
+                "anti_knight" => self.constraints.globals.anti_knight |= !matches!(value, Value::Null | Value::Bool(false)),
+                "anti_king"   => self.constraints.globals.anti_king |= !matches!(value, Value::Null | Value::Bool(false)),
+                key if COSMETIC.contains(&key) => {},
+                key => if enabled(value) { self.unsupported(key.to_string()) },
+            }
+        }
+
+        return Ok(Import {
+            input: api::Input {
+                domains,
+                constraints: self.constraints,
+                config: Config::default(),
+            },
+            unsupported: self.unsupported,
+        });
+    }
+
+    fn domains(&mut self, grid: Option<&Value>) -> Result<api::Domains, Error> {
+        let rows = grid.and_then(Value::as_array)
+            .filter(|rows| rows.len() == 9)
+            .ok_or_else(|| malformed("expected a grid of 9 rows".to_string()).with_field("grid".to_string()))?;
+
+        let mut domains = api::Domains::new();
+        for (r, row) in rows.iter().enumerate() {
+            let row = row.as_array()
+                .filter(|row| row.len() == 9)
+                .ok_or_else(|| malformed("expected a row of 9 cells".to_string()).with_field(format!("grid[{}]", r)))?;
+            for (c, cell) in row.iter().enumerate() {
+                let name = format!("{}:{}", r + 1, c + 1);
+                let field = format!("grid[{}][{}]", r, c);
+                let digit = |value: &Value| match value.as_u64() {
+                    Some(d @ 1..=9) => Ok(d as usize),
+                    _ => Err(Error::invalid_puzzle(format!("invalid digit {}", value)).with_field(field.clone()).with_cell(name.clone())),
+                };
+
+                let given = cell.get("given").and_then(Value::as_bool).unwrap_or(false);
+                let domain = match (cell.get("value"), cell.get("givenPencilMarks")) {
+                    (Some(value), _) if given => vec![digit(value)?],
+                    (_, Some(Value::Array(marks))) if !marks.is_empty() => {
+                        let mut domain = marks.iter().map(digit).collect::<Result<Vec<_>, _>>()?;
+                        domain.sort();
+                        domain.dedup();
+                        domain
+                    },
+                    _ => (1..=9).collect(),
+                };
+                domains.insert(name, domain);
+
+                if let Some(cell) = cell.as_object() {
+                    for (key, value) in cell.iter() {
+                        if !COSMETIC_CELL.contains(&key.as_str()) && !value.is_null() {
+                            self.unsupported(format!("grid.{}", key));
+                        }
+                    }
+                }
+            }
+        }
+        return Ok(domains);
+    }
+
+    // Entries of the form { "lines": [[cells]] }, where every line is a separate constraint
+    fn lines(&mut self, key: &str, value: &Value, r#type: api::ConstraintType) -> Result<(), Error> {
+        for (i, entry) in entries(value, key)?.iter().enumerate() {
+            let field = format!("{}[{}]", key, i);
+            if key == "whispers" && entry.get("value").is_some_and(|v| !(v.as_str() == Some("5") || v.as_u64() == Some(5))) {
+                self.unsupported("whispers with a difference other than 5".to_string());
+                continue;
+            }
+            let lines = entry.get("lines").and_then(Value::as_array)
+                .ok_or_else(|| malformed("expected lines".to_string()).with_field(field.clone()))?;
+            for line in lines.iter() {
+                self.constraints.locals.push(api::Constraint {
+                    r#type: r#type.clone(),
+                    cells: cells(line, &field)?,
+                });
+            }
+        }
+        return Ok(());
+    }
+
+    // Entries of the form { "cells": [cell, cell], "value": "..." }, typed by their value
+    fn dots(&mut self, key: &str, value: &Value, r#type: impl Fn(Option<&str>) -> Option<api::ConstraintType>) -> Result<(), Error> {
+        for (i, entry) in entries(value, key)?.iter().enumerate() {
+            let field = format!("{}[{}]", key, i);
+            let label = match entry.get("value") {
+                Some(Value::String(label)) if !label.is_empty() => Some(label.clone()),
+                Some(Value::Number(label)) => Some(label.to_string()),
+                _ => None,
+            };
+            let r#type = match r#type(label.as_deref()) {
+                Some(r#type) => r#type,
+                None => {
+                    self.unsupported(format!("{} with value {}", key, label.unwrap_or_default()));
+                    continue;
+                },
+            };
+            let names = entry.get("cells").ok_or_else(|| malformed("expected cells".to_string()).with_field(field.clone()))?;
+            self.constraints.locals.push(api::Constraint {
+                r#type,
+                cells: cells(names, &field)?,
+            });
+        }
+        return Ok(());
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn grid() -> Value {
+        let mut grid = vec![vec![json!({}); 9]; 9];
+        grid[0][0] = json!({ "value": 4, "given": true });
+        grid[0][1] = json!({ "value": 8 });
+        grid[0][2] = json!({ "givenPencilMarks": [3, 1] });
+        return json!(grid);
+    }
+
+    #[test]
+    fn test_import() {
+        let puzzle = json!({
+            "size": 9,
+            "title": "Test",
+            "grid": grid(),
+            "thermometer": [{ "lines": [["R1C1", "R1C2", "R1C3"], ["R1C1", "R2C1"]] }],
+            "palindrome": [{ "lines": [["R5C5", "R5C6"]] }],
+            "whispers": [{ "lines": [["R6C1", "R6C2"]] }],
+            "difference": [{ "cells": ["R9C1", "R9C2"] }],
+            "ratio": [{ "cells": ["R9C3", "R9C4"], "value": "2" }],
+            "xv": [{ "cells": ["R8C1", "R8C2"], "value": "X" }, { "cells": ["R8C3", "R8C4"], "value": "V" }],
+            "antiknight": true,
+            "antiking": false,
+            "line": [{ "lines": [["R5C5", "R5C6"]] }],
+        });
+        let url = format!("{}{}", URL_PREFIX, compress(&puzzle));
+        let import = import(&url).unwrap();
+        assert!(import.unsupported.is_empty());
+
+        let input = import.input;
+        assert_eq!(input.domains["1:1"], vec![4]);
+        assert_eq!(input.domains["1:2"].len(), 9);
+        assert_eq!(input.domains["1:3"], vec![1, 3]);
+        assert!(input.constraints.globals.anti_knight);
+        assert!(!input.constraints.globals.anti_king);
+
+        let types = input.constraints.locals.iter().map(|c| c.r#type.name()).collect::<Vec<_>>();
+        assert_eq!(types.iter().filter(|t| **t == "thermometer").count(), 2);
+        for t in ["palindrome", "whisper", "white_kropki", "black_kropki", "x", "v"] {
+            assert!(types.contains(&t), "missing {}", t);
+        }
+        let thermometer = input.constraints.locals.iter().find(|c| c.r#type.name() == "thermometer").unwrap();
+        assert_eq!(thermometer.cells, vec!["1:1", "1:2", "1:3"]);
+
+        // Other forms of the same data
+        let ctc = format!("https://app.crackingthecryptic.com/sudoku/?puzzleid=fpuzzles{}", compress(&puzzle).replace('+', "%2B"));
+        assert_eq!(super::import(&ctc).unwrap().input.constraints.locals.len(), input.constraints.locals.len());
+        assert!(super::import(&compress(&puzzle)).is_ok());
+        assert!(super::import(&puzzle.to_string()).is_ok());
+
+        assert!(crate::puzzles::sudoku::solve(input).is_ok());
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let puzzle = json!({
+            "size": 9,
+            "grid": grid(),
+            "arrow": [{ "lines": [["R1C1", "R1C2"]], "cells": ["R1C1"] }],
+            "killercage": [],
+            "diagonal+": true,
+            "diagonal-": false,
+            "ratio": [{ "cells": ["R9C3", "R9C4"], "value": "3" }],
+            "renban": [{ "lines": [["R2C1", "R2C2"]] }],
+        });
+        let import = import(&compress(&puzzle)).unwrap();
+        assert_eq!(import.unsupported, vec!["arrow", "diagonal+", "ratio with value 3"]);
+        assert_eq!(import.input.constraints.locals.len(), 1);
+        assert_eq!(import.strict().unwrap_err().code, ErrorCode::Unsupported);
+
+        let puzzle = json!({ "size": 6, "grid": [] });
+        assert_eq!(super::import(&puzzle.to_string()).unwrap_err().code, ErrorCode::Unsupported);
+        assert_eq!(super::import("https://sudokupad.app/scl123").unwrap_err().code, ErrorCode::Unsupported);
+        assert_eq!(super::import("not compressed!").unwrap_err().code, ErrorCode::MalformedInput);
+
+        let puzzle = json!({ "size": 9, "grid": grid(), "renban": [{ "lines": [["R2C1", "R10C2"]] }] });
+        assert_eq!(super::import(&puzzle.to_string()).unwrap_err().code, ErrorCode::InvalidPuzzle);
+    }
+
+    #[test]
+    fn test_whispers_difference() {
+        // Only German whispers, with a difference of 5, whether the value is a string or a number
+        for value in [json!("3"), json!(3)] {
+            let puzzle = json!({
+                "size": 9,
+                "grid": grid(),
+                "whispers": [{ "lines": [["R6C1", "R6C2"]], "value": value }, { "lines": [["R7C1", "R7C2"]], "value": 5 }],
+            });
+            let import = import(&compress(&puzzle)).unwrap();
+            assert_eq!(import.unsupported, vec!["whispers with a difference other than 5"], "{}", value);
+            assert_eq!(import.input.constraints.locals.len(), 1);
+            assert_eq!(import.input.constraints.locals[0].cells, vec!["7:1", "7:2"]);
+        }
+    }
+
+    #[test]
+    fn test_python_export() {
+        // As written by Sudoku.to_json in the Python frontend
+        let puzzle = json!({
+            "title": "knight", "author": "Dave", "ruleset": "", "size": 9,
+            "grid": grid(), "thermometer": [], "palindrome": [], "line": [],
+            "anti_knight": {},
+        });
+        let import = import(&compress(&puzzle)).unwrap();
+        assert!(import.unsupported.is_empty());
+        assert!(import.input.constraints.globals.anti_knight);
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let puzzle = json!({
+            "size": 9,
+            "grid": grid(),
+            "thermometer": [{ "lines": [["R1C1", "R1C2", "R1C3"]] }],
+            "palindrome": [{ "lines": [["R5C5", "R5C6"]] }],
+            "renban": [{ "lines": [["R2C1", "R2C2", "R2C3"]] }],
+            "whispers": [{ "lines": [["R6C1", "R6C2"]] }],
+            "difference": [{ "cells": ["R9C1", "R9C2"] }],
+            "ratio": [{ "cells": ["R9C3", "R9C4"] }],
+            "xv": [{ "cells": ["R8C1", "R8C2"], "value": "X" }, { "cells": ["R8C3", "R8C4"], "value": "V" }],
+            "antiknight": true,
+            "antiking": true,
+        });
+        let input = import(&compress(&puzzle)).unwrap().strict().unwrap();
+        let exported = export(&input).unwrap();
+        // Values the player entered aren't part of the puzzle, and pencil marks are sorted
+        let mut expected = puzzle.clone();
+        expected["grid"][0][1] = json!({});
+        expected["grid"][0][2] = json!({ "givenPencilMarks": [1, 3] });
+        assert_eq!(exported, expected);
+        let puzzle = expected;
+
+        let url = to_url(&input).unwrap();
+        assert!(url.starts_with(URL_PREFIX));
+        let reimported = import(&url).unwrap().strict().unwrap();
+        assert_eq!(reimported.domains, input.domains);
+        assert_eq!(export(&reimported).unwrap(), puzzle);
+
+        let mut input = reimported;
+        input.constraints.locals[0].cells.push("10:1".to_string());
+        assert_eq!(export(&input).unwrap_err().field, Some("constraints.locals[0]".to_string()));
+
+        let mut input = import(&url).unwrap().strict().unwrap();
+        input.domains.insert("4:5".to_string(), Vec::new());
+        let err = export(&input).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidPuzzle);
+        assert_eq!(err.cell, Some("4:5".to_string()));
+        assert!(to_url(&input).is_err());
+    }
+
+}
@@ -0,0 +1,405 @@
+// Generates sudokus with a unique solution.
+//
+// A random solution grid is found for the requested constraints, local constraints that hold in that
+// solution are placed at random, and then givens are removed in random order for as long as the
+// solution stays unique and the puzzle doesn't get harder than the target difficulty.
+
+use super::{api, adjacent, count_solutions, orthogonally_adjacent, Converter};
+use super::file::{self, PuzzleFile};
+use crate::branching::{Branching, Rng, ValueOrdering};
+use crate::error::Error;
+use crate::solver::{Config, SolveResult, Stats};
+use crate::types::Variable;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+
+// Guesses allowed when looking for a solution grid, checking uniqueness or rating, so one bad attempt
+// can't hold up generation
+const MAX_NODES: u64 = 20_000;
+
+// Random walks tried when placing a line
+const LINE_TRIES: usize = 2_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    // Solved by propagation alone
+    Easy,
+    // Solved with one or two rounds of guessing
+    #[default]
+    Medium,
+    // Solved with more rounds of guessing
+    Hard,
+    // Unique, but beyond the solver's deductions
+    Expert,
+}
+
+#[derive(Debug)]
+pub struct Request {
+    pub globals: api::GlobalConstraints,
+    // Constraints every puzzle has
+    pub locals: Vec<api::Constraint>,
+    // How many constraints of each type to place at random
+    pub random: BTreeMap<api::ConstraintType, usize>,
+    pub difficulty: Difficulty,
+    pub seed: u64,
+    // Solution grids to try before settling for the closest difficulty
+    pub attempts: usize,
+}
+
+impl Default for Request {
+
+    fn default() -> Self {
+        Request {
+            globals: api::GlobalConstraints::default(),
+            locals: Vec::new(),
+            random: BTreeMap::new(),
+            difficulty: Difficulty::default(),
+            seed: 0,
+            attempts: 20,
+        }
+    }
+
+}
+
+#[derive(Serialize, Debug)]
+pub struct Generated {
+    #[serde(flatten)]
+    pub puzzle: PuzzleFile,
+    #[serde(serialize_with = "file::sorted")]
+    pub solution: api::Domains,
+    pub givens: usize,
+    pub difficulty: Difficulty,
+    // Generating from the same request with this seed gives the same puzzle
+    pub seed: u64,
+    // From solving the puzzle, as used for the rating
+    pub stats: Stats,
+}
+
+fn cell_name(variable: Variable) -> String {
+    format!("{}:{}", variable / 9 + 1, variable % 9 + 1)
+}
+
+// Domains with the given digits, and every other cell blank
+fn domains(grid: &[Option<usize>]) -> api::Domains {
+    grid.iter().enumerate().map(|(variable, digit)| {
+        let domain = match digit {
+            Some(digit) => vec![*digit],
+            None => (1..=9).collect(),
+        };
+        (cell_name(variable), domain)
+    }).collect()
+}
+
+// How hard the solver finds a puzzle, assuming its solution is unique.
+// None if it takes more guesses than generation allows.
+pub fn rate(domains: &api::Domains, constraints: &api::Constraints) -> Result<Option<(Difficulty, Stats)>, Error> {
+    return rate_within(domains, constraints, MAX_NODES);
+}
+
+fn rate_within(domains: &api::Domains, constraints: &api::Constraints, max_nodes: u64) -> Result<Option<(Difficulty, Stats)>, Error> {
+    let input = api::Input {
+        domains: domains.clone(),
+        constraints: constraints.clone(),
+        config: Config { max_nodes: Some(max_nodes), ..Config::default() },
+    };
+    let output = super::solve(input)?;
+    let difficulty = match output.result {
+        SolveResult::Solved if output.stats.guesses == 0 => Difficulty::Easy,
+        SolveResult::Solved if output.stats.guessing_rounds <= 2 => Difficulty::Medium,
+        SolveResult::Solved => Difficulty::Hard,
+        SolveResult::Timeout => return Ok(None),
+        _ => Difficulty::Expert,
+    };
+    return Ok(Some((difficulty, output.stats)));
+}
+
+fn unique(domains: &api::Domains, constraints: &api::Constraints) -> Result<bool, Error> {
+    let config = Config { branching: Branching::Mrv, max_nodes: Some(MAX_NODES), ..Config::default() };
+    let (solutions, _) = count_solutions(domains, constraints, config, 2)?;
+    return Ok(solutions.count == 1 && !solutions.timeout);
+}
+
+// Whether digits placed on a constraint's cells satisfy it
+fn holds(r#type: &api::ConstraintType, digits: &[usize]) -> bool {
+    match r#type {
+        api::ConstraintType::WhiteKropki => digits[0].abs_diff(digits[1]) == 1,
+        api::ConstraintType::BlackKropki => digits[0] == 2 * digits[1] || digits[1] == 2 * digits[0],
+        api::ConstraintType::X           => digits[0] + digits[1] == 10,
+        api::ConstraintType::V           => digits[0] + digits[1] == 5,
+        api::ConstraintType::Thermometer => digits.windows(2).all(|pair| pair[0] < pair[1]),
+        api::ConstraintType::Palindrome  => digits.iter().eq(digits.iter().rev()),
+        api::ConstraintType::Renban      => {
+            let (min, max) = (digits.iter().min().unwrap(), digits.iter().max().unwrap());
+            let mut sorted = digits.to_vec();
+            sorted.sort();
+            sorted.dedup();
+            sorted.len() == digits.len() && max - min + 1 == digits.len()
+        },
+        api::ConstraintType::Whisper     => digits.windows(2).all(|pair| pair[0].abs_diff(pair[1]) >= 5),
+    }
+}
+
+// Lengths of randomly placed lines
+fn line_lengths(r#type: &api::ConstraintType) -> Option<(usize, usize)> {
+    match r#type {
+        api::ConstraintType::Thermometer => Some((3, 6)),
+        api::ConstraintType::Palindrome  => Some((3, 5)),
+        api::ConstraintType::Renban      => Some((3, 5)),
+        api::ConstraintType::Whisper     => Some((3, 6)),
+        _ => None,
+    }
+}
+
+// Places constraints that hold in a solution, without overlapping each other
+struct Placer<'a> {
+    solution: &'a [usize],
+    rng: &'a mut Rng,
+    // Cells on lines, and pairs of cells joined by a dot
+    line_cells: Vec<bool>,
+    edges: Vec<(Variable, Variable)>,
+}
+
+impl Placer<'_> {
+
+    fn reserve(&mut self, r#type: &api::ConstraintType, cells: &[Variable]) {
+        if line_lengths(r#type).is_some() {
+            for cell in cells.iter() {
+                self.line_cells[*cell] = true;
+            }
+        } else {
+            self.edges.push((cells[0].min(cells[1]), cells[0].max(cells[1])));
+        }
+    }
+
+    fn digits(&self, cells: &[Variable]) -> Vec<usize> {
+        cells.iter().map(|cell| self.solution[*cell]).collect()
+    }
+
+    fn place(&mut self, r#type: &api::ConstraintType) -> Option<Vec<Variable>> {
+        let cells = match line_lengths(r#type) {
+            Some((min, max)) => self.place_line(r#type, min, max),
+            None => self.place_edge(r#type),
+        };
+        if let Some(cells) = &cells {
+            self.reserve(r#type, cells);
+        }
+        return cells;
+    }
+
+    fn place_edge(&mut self, r#type: &api::ConstraintType) -> Option<Vec<Variable>> {
+        let mut candidates = Vec::new();
+        for v1 in 0..81 {
+            for v2 in [v1 + 1, v1 + 9] {
+                if v2 < 81 && orthogonally_adjacent(v1, v2) && !self.edges.contains(&(v1, v2)) && holds(r#type, &self.digits(&[v1, v2])) {
+                    candidates.push(vec![v1, v2]);
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return None;
+        }
+        let i = self.rng.below(candidates.len());
+        return Some(candidates.swap_remove(i));
+    }
+
+    fn place_line(&mut self, r#type: &api::ConstraintType, min: usize, max: usize) -> Option<Vec<Variable>> {
+        for _ in 0..LINE_TRIES {
+            let length = min + self.rng.below(max - min + 1);
+            let mut cells = vec![self.rng.below(81)];
+            if self.line_cells[cells[0]] {
+                continue;
+            }
+            while cells.len() < length {
+                let last = *cells.last().unwrap();
+                let next = (0..81)
+                    .filter(|v| adjacent(last, *v) && !self.line_cells[*v] && !cells.contains(v))
+                    .collect::<Vec<_>>();
+                if next.is_empty() {
+                    break;
+                }
+                cells.push(next[self.rng.below(next.len())]);
+            }
+            if cells.len() == length && holds(r#type, &self.digits(&cells)) {
+                return Some(cells);
+            }
+        }
+        return None;
+    }
+
+}
+
+// One solution grid, with givens removed as far as the target difficulty allows.
+// None if the random constraints couldn't be placed in this grid.
+fn attempt(request: &Request, rng: &mut Rng) -> Result<Option<Generated>, Error> {
+    let mut constraints = api::Constraints {
+        globals: request.globals.clone(),
+        locals: request.locals.clone(),
+    };
+    let blank = vec![None; 81];
+
+    let config = Config {
+        branching: Branching::Mrv,
+        value_ordering: ValueOrdering::Random,
+        seed: rng.next(),
+        max_nodes: Some(MAX_NODES),
+        ..Config::default()
+    };
+    let (solutions, _) = count_solutions(&domains(&blank), &constraints, config, 1)?;
+    let solution = match solutions.first {
+        Some(first) => first.iter().map(|domain| domain.iter().next().unwrap()).collect::<Vec<_>>(),
+        None if solutions.timeout => return Ok(None),
+        None => return Err(Error::invalid_puzzle("the requested constraints have no solution".to_string())),
+    };
+
+    let mut placer = Placer { solution: &solution, rng, line_cells: vec![false; 81], edges: Vec::new() };
+    for constraint in request.locals.iter() {
+        let cells = constraint.cells.iter().map(|cell| {
+            let (r, c) = cell.split_once(':').unwrap();
+            (r.parse::<usize>().unwrap() - 1) * 9 + c.parse::<usize>().unwrap() - 1
+        }).collect::<Vec<_>>();
+        placer.reserve(&constraint.r#type, &cells);
+    }
+    for (r#type, count) in request.random.iter() {
+        for _ in 0..*count {
+            match placer.place(r#type) {
+                Some(cells) => constraints.locals.push(api::Constraint {
+                    r#type: r#type.clone(),
+                    cells: cells.into_iter().map(cell_name).collect(),
+                }),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    let mut grid = solution.iter().map(|digit| Some(*digit)).collect::<Vec<_>>();
+    let mut order = (0..81).collect::<Vec<_>>();
+    rng.shuffle(&mut order);
+    for cell in order {
+        grid[cell] = None;
+        let puzzle = domains(&grid);
+        // Too hard to rate counts as too hard, even for expert puzzles
+        let keep = !unique(&puzzle, &constraints)?
+            || rate(&puzzle, &constraints)?.is_none_or(|(difficulty, _)| difficulty > request.difficulty);
+        if keep {
+            grid[cell] = Some(solution[cell]);
+        }
+    }
+
+    let puzzle = domains(&grid);
+    let (difficulty, stats) = match rate(&puzzle, &constraints)? {
+        Some(rating) => rating,
+        None => return Ok(None),
+    };
+    return Ok(Some(Generated {
+        puzzle: PuzzleFile::new(api::Input { domains: puzzle, constraints, config: Config::default() }, None),
+        solution: domains(&solution.iter().map(|digit| Some(*digit)).collect::<Vec<_>>()),
+        givens: grid.iter().filter(|digit| digit.is_some()).count(),
+        difficulty,
+        seed: request.seed,
+        stats,
+    }));
+}
+
+// Generate a puzzle of the requested difficulty, or the closest one found within the allowed attempts
+pub fn generate(request: &Request) -> Result<Generated, Error> {
+    let constraints = api::Constraints {
+        globals: request.globals.clone(),
+        locals: request.locals.clone(),
+    };
+    Converter::new(&domains(&[None; 81]), &constraints)?;
+
+    let mut rng = Rng::new(request.seed);
+    let mut best : Option<Generated> = None;
+    for _ in 0..request.attempts.max(1) {
+        let generated = match attempt(request, &mut rng)? {
+            Some(generated) => generated,
+            None => continue,
+        };
+        if generated.difficulty == request.difficulty {
+            return Ok(generated);
+        }
+        let distance = |generated: &Generated| (generated.difficulty as usize).abs_diff(request.difficulty as usize);
+        if best.as_ref().is_none_or(|best| distance(&generated) < distance(best)) {
+            best = Some(generated);
+        }
+    }
+    return best.ok_or_else(|| Error::invalid_puzzle("couldn't place the requested constraints".to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::puzzles::sudoku::text;
+
+    #[test]
+    fn test_generate_classic() {
+        let request = Request { difficulty: Difficulty::Easy, seed: 1, ..Request::default() };
+        let generated = generate(&request).unwrap();
+        assert_eq!(generated.difficulty, Difficulty::Easy);
+        assert!(generated.givens < 40);
+        assert!(unique(&generated.puzzle.domains, &generated.puzzle.constraints).unwrap());
+
+        // Every given is part of the solution
+        for (cell, domain) in generated.puzzle.domains.iter() {
+            if domain.len() == 1 {
+                assert_eq!(domain, &generated.solution[cell]);
+            }
+        }
+
+        let again = generate(&request).unwrap();
+        assert_eq!(again.puzzle.domains, generated.puzzle.domains);
+    }
+
+    #[test]
+    fn test_generate_variant() {
+        let request = Request {
+            globals: api::GlobalConstraints { anti_knight: true, anti_king: false },
+            random: [(api::ConstraintType::Thermometer, 2), (api::ConstraintType::WhiteKropki, 3)].into_iter().collect(),
+            difficulty: Difficulty::Medium,
+            seed: 7,
+            attempts: 1,
+            ..Request::default()
+        };
+        let generated = generate(&request).unwrap();
+        let constraints = &generated.puzzle.constraints;
+        assert!(constraints.globals.anti_knight);
+        assert_eq!(constraints.locals.iter().filter(|c| c.r#type.name() == "thermometer").count(), 2);
+        assert_eq!(constraints.locals.iter().filter(|c| c.r#type.name() == "white_kropki").count(), 3);
+        assert!(unique(&generated.puzzle.domains, constraints).unwrap());
+
+        // The placed constraints hold in the solution
+        for constraint in constraints.locals.iter() {
+            let digits = constraint.cells.iter().map(|cell| generated.solution[cell][0]).collect::<Vec<_>>();
+            assert!(holds(&constraint.r#type, &digits));
+        }
+    }
+
+    #[test]
+    fn test_rate() {
+        let puzzle = text::parse("48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....").unwrap();
+        let constraints = api::Constraints::default();
+        let (difficulty, stats) = rate(&puzzle, &constraints).unwrap().unwrap();
+        assert!(difficulty > Difficulty::Easy);
+        assert!(stats.guesses > 1);
+
+        // Running out of guesses isn't a rating
+        assert!(rate_within(&puzzle, &constraints, 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_impossible_request() {
+        let request = Request {
+            globals: api::GlobalConstraints { anti_knight: false, anti_king: false },
+            locals: vec![api::Constraint { r#type: api::ConstraintType::V, cells: vec!["1:1".to_string(), "1:2".to_string()] }],
+            random: [(api::ConstraintType::V, 200)].into_iter().collect(),
+            attempts: 1,
+            ..Request::default()
+        };
+        assert!(generate(&request).is_err());
+    }
+
+}
@@ -0,0 +1,176 @@
+// Removes givens from a uniquely solvable sudoku until every remaining given is needed for uniqueness.
+//
+// A given whose removal alone allows another solution is essential: every unique reduction keeps it,
+// since removing other givens as well only allows more solutions. Essential givens are found first,
+// then the rest are removed one at a time, in row-major or shuffled order, as long as the solution
+// stays unique. Different orders can give different (equally minimal) puzzles.
+
+use super::{api, count_solutions};
+use super::file::{self, PuzzleFile};
+use crate::branching::Rng;
+use crate::error::Error;
+use crate::solver::Config;
+
+use serde::Serialize;
+
+
+#[derive(Serialize, Debug)]
+pub struct Reduced {
+    #[serde(flatten)]
+    pub puzzle: PuzzleFile,
+    #[serde(serialize_with = "file::sorted")]
+    pub solution: api::Domains,
+    pub givens: usize,
+    // Givens of the original that every unique reduction keeps
+    pub essential: Vec<api::Cell>,
+    // Givens of the original that were removed
+    pub removed: Vec<api::Cell>,
+    // Givens kept because checking them hit the configured time or node limit
+    pub undecided: Vec<api::Cell>,
+}
+
+enum Uniqueness {
+    Unique(api::Domains),
+    NotUnique,
+    Unknown,
+}
+
+fn uniqueness(domains: &api::Domains, constraints: &api::Constraints, config: Config) -> Result<Uniqueness, Error> {
+    let (solutions, _) = count_solutions(domains, constraints, config, 2)?;
+    if solutions.timeout {
+        return Ok(Uniqueness::Unknown);
+    }
+    if solutions.count != 1 {
+        return Ok(Uniqueness::NotUnique);
+    }
+    let solution = solutions.first.unwrap().iter().enumerate()
+        .map(|(variable, domain)| (format!("{}:{}", variable / 9 + 1, variable % 9 + 1), domain.iter().collect()))
+        .collect();
+    return Ok(Uniqueness::Unique(solution));
+}
+
+// Reduce a puzzle, taking givens in row-major order, or shuffled by the seed if there is one.
+// The config's limits apply to each uniqueness check.
+pub fn reduce(input: &api::Input, seed: Option<u64>) -> Result<Reduced, Error> {
+    let config = input.config;
+    let solution = match uniqueness(&input.domains, &input.constraints, config)? {
+        Uniqueness::Unique(solution) => solution,
+        Uniqueness::NotUnique => return Err(Error::invalid_puzzle("puzzle doesn't have a unique solution".to_string())),
+        Uniqueness::Unknown => return Err(Error::invalid_puzzle("couldn't find a unique solution within the configured limits".to_string())),
+    };
+
+    let mut givens = (1..=9)
+        .flat_map(|r| (1..=9).map(move |c| format!("{}:{}", r, c)))
+        .filter(|cell| input.domains.get(cell).is_some_and(|domain| domain.len() == 1))
+        .collect::<Vec<_>>();
+    if let Some(seed) = seed {
+        Rng::new(seed).shuffle(&mut givens);
+    }
+
+    let mut domains = input.domains.clone();
+    let mut essential = Vec::new();
+    let mut undecided = Vec::new();
+    for cell in givens.iter() {
+        let given = domains.insert(cell.clone(), (1..=9).collect()).unwrap();
+        match uniqueness(&domains, &input.constraints, config)? {
+            Uniqueness::Unique(_) => {},
+            Uniqueness::NotUnique => essential.push(cell.clone()),
+            Uniqueness::Unknown => undecided.push(cell.clone()),
+        }
+        domains.insert(cell.clone(), given);
+    }
+
+    let candidates = givens.iter().filter(|cell| !essential.contains(cell) && !undecided.contains(cell)).cloned().collect::<Vec<_>>();
+    let mut removed = Vec::new();
+    for cell in candidates.iter() {
+        let given = domains.insert(cell.clone(), (1..=9).collect()).unwrap();
+        match uniqueness(&domains, &input.constraints, config)? {
+            Uniqueness::Unique(_) => removed.push(cell.clone()),
+            Uniqueness::NotUnique => { domains.insert(cell.clone(), given); },
+            Uniqueness::Unknown => {
+                domains.insert(cell.clone(), given);
+                undecided.push(cell.clone());
+            },
+        }
+    }
+
+    return Ok(Reduced {
+        givens: givens.len() - removed.len(),
+        puzzle: PuzzleFile::new(api::Input { domains, constraints: input.constraints.clone(), config }, None),
+        solution,
+        essential,
+        removed,
+        undecided,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::puzzles::sudoku::text;
+
+    const SOLUTION : &str = "487312695593684271126597384735849162914265837268731549851476923379128456642953718";
+
+    fn input(line: &str, constraints: api::Constraints) -> api::Input {
+        api::Input { domains: text::parse(line).unwrap(), constraints, config: Config::default() }
+    }
+
+    // No remaining given can go, and each is part of the solution
+    fn assert_minimal(reduced: &Reduced) {
+        let domains = &reduced.puzzle.domains;
+        for (cell, domain) in domains.iter().filter(|(_, domain)| domain.len() == 1) {
+            let mut domains = domains.clone();
+            domains.insert(cell.clone(), (1..=9).collect());
+            assert!(matches!(uniqueness(&domains, &reduced.puzzle.constraints, Config::default()).unwrap(), Uniqueness::NotUnique));
+            assert_eq!(domain, &reduced.solution[cell]);
+        }
+    }
+
+    #[test]
+    fn test_reduce_solved_grid() {
+        let reduced = reduce(&input(SOLUTION, api::Constraints::default()), Some(3)).unwrap();
+        assert_eq!(text::to_line(&reduced.solution), SOLUTION);
+        assert!(reduced.givens < 30);
+        assert_eq!(reduced.givens + reduced.removed.len(), 81);
+        assert!(reduced.undecided.is_empty());
+        assert_minimal(&reduced);
+    }
+
+    #[test]
+    fn test_reduce_variant() {
+        let constraints = api::Constraints {
+            globals: api::GlobalConstraints { anti_knight: false, anti_king: false },
+            locals: vec![
+                api::Constraint { r#type: api::ConstraintType::Thermometer, cells: vec!["3:1".to_string(), "3:2".to_string(), "2:1".to_string(), "1:2".to_string()] },
+                api::Constraint { r#type: api::ConstraintType::X, cells: vec!["9:1".to_string(), "9:2".to_string()] },
+            ],
+        };
+        let reduced = reduce(&input(SOLUTION, constraints), None).unwrap();
+        assert_eq!(text::to_line(&reduced.solution), SOLUTION);
+        assert_minimal(&reduced);
+        assert!(reduced.essential.iter().all(|cell| reduced.puzzle.domains[cell].len() == 1));
+
+        // Constraints the solution breaks leave nothing to reduce
+        let constraints = api::Constraints {
+            globals: api::GlobalConstraints { anti_knight: false, anti_king: true },
+            locals: Vec::new(),
+        };
+        assert!(reduce(&input(SOLUTION, constraints), None).is_err());
+    }
+
+    #[test]
+    fn test_reduce_minimal_puzzle() {
+        let puzzle = "48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....";
+        let reduced = reduce(&input(puzzle, api::Constraints::default()), None).unwrap();
+        // Already minimal, so every given is essential
+        assert!(reduced.removed.is_empty());
+        assert_eq!(reduced.essential.len(), 17);
+
+        let mut domains = text::parse(puzzle).unwrap();
+        domains.insert("1:1".to_string(), (1..=9).collect());
+        let err = reduce(&api::Input { domains, constraints: api::Constraints::default(), config: Config::default() }, None).unwrap_err();
+        assert_eq!(err.code, crate::error::ErrorCode::InvalidPuzzle);
+    }
+
+}
@@ -0,0 +1,503 @@
+// Canonical forms of sudokus: puzzles that are transforms of one another share one, so duplicates can be
+// found.
+//
+// A plain sudoku stays a sudoku when its digits are relabelled, its rows are reordered within a band (or
+// columns within a stack), its bands (or stacks) are reordered, or it is transposed; rotations and
+// reflections are combinations of those. Constraints allow fewer transforms. Lines and dots drawn on the
+// grid, like the anti-knight and anti-king rules, only survive the 8 rotations and reflections of the whole
+// grid. Constraints on values only survive some relabellings: swapping each digit d for 10 - d keeps white
+// kropki dots, X, thermometers (running the other way), renbans and whispers, while black kropki dots and V
+// need the digits as they are.
+//
+// The canonical form is the smallest puzzle the allowed transforms reach, comparing cells in row-major order
+// (blank before given, givens by digit, then pencil marks) and then the constraints. Digits are relabelled
+// in the order they first appear, which needs every cell to be blank or given; with pencil marks, the
+// digits are kept as they are.
+
+use super::{api, Converter};
+use crate::error::Error;
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+
+const PERMUTATIONS: [[usize; 3]; 6] = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+
+const IDENTITY: [usize; 9] = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+const REVERSED: [usize; 9] = [8, 7, 6, 5, 4, 3, 2, 1, 0];
+
+const DIGITS: [usize; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+const COMPLEMENT: [usize; 10] = [0, 9, 8, 7, 6, 5, 4, 3, 2, 1];
+
+// Candidates of a cell, as bits 1-9
+type Mask = u16;
+
+const BLANK: Mask = 0b11_1111_1110;
+
+// Greater than any cell, for rows not found yet
+const UNSEEN: [u16; 9] = [u16::MAX; 9];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transform {
+    transpose: bool,
+    // Cell (r, c) of the result is cell (rows[r], cols[c]) of the puzzle, after transposing it if `transpose`
+    rows: [usize; 9],
+    cols: [usize; 9],
+    // What each digit becomes (0 is unused)
+    digits: [usize; 10],
+}
+
+// The relabellings the constraints allow
+enum Relabelling {
+    Any,
+    Complement,
+    None,
+}
+
+#[derive(Debug)]
+pub struct Canonical {
+    pub domains: api::Domains,
+    pub constraints: api::Constraints,
+    // Takes the puzzle to its canonical form
+    pub transform: Transform,
+}
+
+impl Canonical {
+
+    // The same for puzzles that are transforms of one another
+    pub fn key(&self) -> String {
+        let domains = self.domains.iter().collect::<BTreeMap<_, _>>();
+        // Only strings, numbers and the like, which always serialize
+        return serde_json::to_string(&(domains, &self.constraints)).unwrap();
+    }
+
+}
+
+fn cell_name(i: usize) -> String {
+    format!("{}:{}", i / 9 + 1, i % 9 + 1)
+}
+
+fn cell_index(cell: &str) -> usize {
+    let (r, c) = cell.split_once(':').unwrap();
+    return (r.parse::<usize>().unwrap() - 1) * 9 + c.parse::<usize>().unwrap() - 1;
+}
+
+// How a cell compares: blank cells first, then givens by digit, then pencil marks
+fn code(mask: Mask) -> u16 {
+    if mask == BLANK {
+        return 0;
+    }
+    if mask.count_ones() == 1 {
+        return mask.trailing_zeros() as u16;
+    }
+    return 16 + mask;
+}
+
+fn relabel(mask: Mask, digits: &[usize; 10]) -> Mask {
+    (1..=9).filter(|digit| mask & (1 << digit) != 0).fold(0, |relabelled, digit| relabelled | (1 << digits[digit]))
+}
+
+// Each of the orders of 9 rows (or columns) that keep bands (or stacks) together
+fn orders() -> Vec<[usize; 9]> {
+    let mut orders = Vec::with_capacity(6 * 6 * 6 * 6);
+    for bands in PERMUTATIONS {
+        for first in PERMUTATIONS {
+            for second in PERMUTATIONS {
+                for third in PERMUTATIONS {
+                    let mut order = [0; 9];
+                    for (i, within) in [first, second, third].iter().enumerate() {
+                        for (j, row) in within.iter().enumerate() {
+                            order[i * 3 + j] = bands[i] * 3 + row;
+                        }
+                    }
+                    orders.push(order);
+                }
+            }
+        }
+    }
+    return orders;
+}
+
+// Labels for the digits that don't appear, in order, so that the relabelling is a permutation
+fn complete(mut labels: [usize; 10], mut next: usize) -> [usize; 10] {
+    for label in labels.iter_mut().skip(1) {
+        if *label == 0 {
+            next += 1;
+            *label = next;
+        }
+    }
+    return labels;
+}
+
+impl Transform {
+
+    // The cell of the puzzle that becomes cell `i`
+    fn source(&self, i: usize) -> usize {
+        let (r, c) = (self.rows[i / 9], self.cols[i % 9]);
+        return if self.transpose { c * 9 + r } else { r * 9 + c };
+    }
+
+    // Where each cell of the puzzle ends up
+    fn targets(&self) -> [usize; 81] {
+        let mut targets = [0; 81];
+        for i in 0..81 {
+            targets[self.source(i)] = i;
+        }
+        return targets;
+    }
+
+    // Takes the transformed puzzle back to the original
+    pub fn inverse(&self) -> Transform {
+        let mut rows = [0; 9];
+        let mut cols = [0; 9];
+        for i in 0..9 {
+            rows[self.rows[i]] = i;
+            cols[self.cols[i]] = i;
+        }
+        let mut digits = [0; 10];
+        for digit in 1..=9 {
+            digits[self.digits[digit]] = digit;
+        }
+        if self.transpose {
+            return Transform { transpose: true, rows: cols, cols: rows, digits };
+        }
+        return Transform { transpose: false, rows, cols, digits };
+    }
+
+    pub fn domains(&self, domains: &api::Domains) -> api::Domains {
+        let targets = self.targets();
+        return domains.iter().map(|(cell, domain)| {
+            let mut digits = domain.iter().map(|digit| self.digits[*digit]).collect::<Vec<_>>();
+            digits.sort();
+            (cell_name(targets[cell_index(cell)]), digits)
+        }).collect();
+    }
+
+    // The constraints moved with the cells, each with its cells in a standard order, sorted
+    pub fn constraints(&self, constraints: &api::Constraints) -> api::Constraints {
+        let targets = self.targets();
+        let mut locals = constraints.locals.iter().map(|local| {
+            let mut cells = local.cells.iter().map(|cell| targets[cell_index(cell)]).collect::<Vec<_>>();
+            match local.r#type {
+                api::ConstraintType::WhiteKropki |
+                api::ConstraintType::BlackKropki |
+                api::ConstraintType::X |
+                api::ConstraintType::V |
+                api::ConstraintType::Renban => cells.sort(),
+                api::ConstraintType::Palindrome |
+                api::ConstraintType::Whisper => {
+                    let reversed = cells.iter().rev().copied().collect::<Vec<_>>();
+                    cells = cells.min(reversed);
+                },
+                // Bulb first, so the digits increase along it, unless they were complemented
+                api::ConstraintType::Thermometer => {
+                    if self.digits == COMPLEMENT {
+                        cells.reverse();
+                    }
+                },
+            }
+            (local.r#type.clone(), cells)
+        }).collect::<Vec<_>>();
+        locals.sort();
+        locals.dedup();
+        return api::Constraints {
+            globals: constraints.globals.clone(),
+            locals: locals.into_iter().map(|(r#type, cells)| api::Constraint { r#type, cells: cells.into_iter().map(cell_name).collect() }).collect(),
+        };
+    }
+
+    fn codes(&self, masks: &[Mask; 81]) -> Vec<u16> {
+        (0..81).map(|i| code(relabel(masks[self.source(i)], &self.digits))).collect()
+    }
+
+}
+
+// Lines (rows, or columns if transposed) that are the same as each other, and bands (or stacks) that are.
+// Swapping two of the same gives back the same puzzle, so they only need trying in one order.
+struct Repeats {
+    lines: [[bool; 9]; 9],
+    bands: [[bool; 3]; 3],
+}
+
+impl Repeats {
+
+    fn new(masks: &[Mask; 81], transpose: bool) -> Self {
+        let line = |i: usize| (0..9).map(move |k| masks[if transpose { k * 9 + i } else { i * 9 + k }]);
+        let mut repeats = Repeats { lines: [[false; 9]; 9], bands: [[false; 3]; 3] };
+        for i in 0..9 {
+            for j in 0..9 {
+                repeats.lines[i][j] = line(i).eq(line(j));
+            }
+        }
+        for a in 0..3 {
+            for b in 0..3 {
+                repeats.bands[a][b] = (0..3).all(|k| repeats.lines[a * 3 + k][b * 3 + k]);
+            }
+        }
+        return repeats;
+    }
+
+    // Whether an order has every repeated line and band after the ones it repeats
+    fn in_order(&self, order: &[usize; 9]) -> bool {
+        for i in 0..9 {
+            for j in i + 1..9 {
+                if i / 3 == j / 3 && self.lines[order[i]][order[j]] && order[i] > order[j] {
+                    return false;
+                }
+            }
+        }
+        for a in 0..3 {
+            for b in a + 1..3 {
+                let (first, second) = (order[a * 3] / 3, order[b * 3] / 3);
+                if self.bands[first][second] && first > second {
+                    return false;
+                }
+            }
+        }
+        return true;
+    }
+
+}
+
+// Finds the smallest plain sudoku reachable by any transform, a row at a time, giving up on a choice of
+// rows as soon as a row comes out bigger than the smallest found so far
+struct Search<'a> {
+    masks: &'a [Mask; 81],
+    relabel: bool,
+    best: [[u16; 9]; 9],
+    transform: Option<Transform>,
+    // The transpose and columns being tried
+    transpose: bool,
+    cols: [usize; 9],
+    repeats: Repeats,
+}
+
+impl<'a> Search<'a> {
+
+    fn new(masks: &'a [Mask; 81], relabel: bool) -> Self {
+        Search { masks, relabel, best: [UNSEEN; 9], transform: None, transpose: false, cols: IDENTITY, repeats: Repeats::new(masks, false) }
+    }
+
+    // Output row `r` onwards, for the rows chosen so far, given the labels so far
+    fn rows(&mut self, rows: &mut [usize; 9], r: usize, labels: [usize; 10], next: usize) {
+        let (transpose, cols, repeats) = (self.transpose, self.cols, &self.repeats);
+        if r == 9 {
+            let digits = if self.relabel { complete(labels, next) } else { DIGITS };
+            self.transform = Some(Transform { transpose, rows: *rows, cols, digits });
+            return;
+        }
+        let band_used = |band: usize| rows[..r - r % 3].iter().any(|used| used / 3 == band);
+        let row_used = |row: usize| rows[r - r % 3..r].contains(&row);
+        let candidates = if r.is_multiple_of(3) {
+            // Any row of a band not used yet, skipping bands that repeat an unused one
+            (0..9).filter(|row| !band_used(row / 3) && !(0..row / 3).any(|band| !band_used(band) && repeats.bands[band][row / 3])).collect::<Vec<_>>()
+        } else {
+            let band = rows[r - 1] / 3;
+            (band * 3..band * 3 + 3).filter(|row| !row_used(*row)).collect()
+        };
+        // Skipping rows that repeat an unused one
+        let candidates = candidates.iter().copied().filter(|row| !(row / 3 * 3..*row).any(|other| !row_used(other) && repeats.lines[other][*row])).collect::<Vec<_>>();
+        for row in candidates {
+            let mut labels = labels;
+            let mut next = next;
+            let mut codes = [0; 9];
+            let mut ordering = Ordering::Equal;
+            for c in 0..9 {
+                let mask = self.masks[if transpose { cols[c] * 9 + row } else { row * 9 + cols[c] }];
+                if self.relabel && mask.count_ones() == 1 {
+                    let digit = mask.trailing_zeros() as usize;
+                    if labels[digit] == 0 {
+                        next += 1;
+                        labels[digit] = next;
+                    }
+                }
+                codes[c] = code(if self.relabel { relabel(mask, &labels) } else { mask });
+                if ordering == Ordering::Equal {
+                    ordering = codes[c].cmp(&self.best[r][c]);
+                    if ordering == Ordering::Greater {
+                        break;
+                    }
+                }
+            }
+            match ordering {
+                Ordering::Greater => continue,
+                Ordering::Less => {
+                    self.best[r] = codes;
+                    for later in self.best[r + 1..].iter_mut() {
+                        *later = UNSEEN;
+                    }
+                },
+                Ordering::Equal => {},
+            }
+            rows[r] = row;
+            self.rows(rows, r + 1, labels, next);
+        }
+    }
+
+    fn run(mut self) -> Transform {
+        let orders = orders();
+        for transpose in [false, true] {
+            self.transpose = transpose;
+            self.repeats = Repeats::new(self.masks, transpose);
+            // Columns are the lines of the puzzle transposed the other way
+            let col_repeats = Repeats::new(self.masks, !transpose);
+            for cols in orders.iter().filter(|cols| col_repeats.in_order(cols)) {
+                self.cols = *cols;
+                self.rows(&mut [0; 9], 0, [0; 10], 0);
+            }
+        }
+        return self.transform.unwrap();
+    }
+
+}
+
+// Relabel digits in the order they first appear in the transformed puzzle
+fn first_appearances(masks: &[Mask; 81], transform: &Transform) -> [usize; 10] {
+    let mut labels = [0; 10];
+    let mut next = 0;
+    for i in 0..81 {
+        let mask = masks[transform.source(i)];
+        let digit = mask.trailing_zeros() as usize;
+        if mask.count_ones() == 1 && labels[digit] == 0 {
+            next += 1;
+            labels[digit] = next;
+        }
+    }
+    return complete(labels, next);
+}
+
+pub fn canonical(domains: &api::Domains, constraints: &api::Constraints) -> Result<Canonical, Error> {
+    // Every cell is present, with digits in range
+    Converter::new(domains, constraints)?;
+    let mut masks = [0; 81];
+    for (cell, domain) in domains.iter() {
+        masks[cell_index(cell)] = domain.iter().fold(0, |mask, digit| mask | (1 << digit));
+    }
+
+    let values = |r#type: &api::ConstraintType| constraints.locals.iter().any(|local| &local.r#type == r#type);
+    let relabelling = if values(&api::ConstraintType::BlackKropki) || values(&api::ConstraintType::V) {
+        Relabelling::None
+    } else if constraints.locals.iter().any(|local| local.r#type != api::ConstraintType::Palindrome) {
+        Relabelling::Complement
+    } else if masks.iter().all(|mask| *mask == BLANK || mask.count_ones() == 1) {
+        Relabelling::Any
+    } else {
+        Relabelling::None
+    };
+    let drawn = !constraints.locals.is_empty() || constraints.globals.anti_knight || constraints.globals.anti_king;
+
+    let transform = if !drawn {
+        Search::new(&masks, matches!(relabelling, Relabelling::Any)).run()
+    } else {
+        let mut transforms = Vec::new();
+        for transpose in [false, true] {
+            for rows in [IDENTITY, REVERSED] {
+                for cols in [IDENTITY, REVERSED] {
+                    let transform = Transform { transpose, rows, cols, digits: DIGITS };
+                    match relabelling {
+                        Relabelling::Any => transforms.push(Transform { digits: first_appearances(&masks, &transform), ..transform }),
+                        Relabelling::Complement => transforms.extend([transform, Transform { digits: COMPLEMENT, ..transform }]),
+                        Relabelling::None => transforms.push(transform),
+                    }
+                }
+            }
+        }
+        let key = |transform: &Transform| {
+            let locals = transform.constraints(constraints).locals.into_iter().map(|local| (local.r#type, local.cells)).collect::<Vec<_>>();
+            (transform.codes(&masks), locals)
+        };
+        transforms.into_iter().min_by_key(key).unwrap()
+    };
+
+    return Ok(Canonical {
+        domains: transform.domains(domains),
+        constraints: transform.constraints(constraints),
+        transform,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::puzzles::sudoku::text;
+
+    const LINE : &str = "48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....";
+
+    fn transform(line: &str, transform: &Transform) -> String {
+        text::to_line(&transform.domains(&text::parse(line).unwrap()))
+    }
+
+    fn canonical_line(line: &str) -> String {
+        text::to_line(&canonical(&text::parse(line).unwrap(), &api::Constraints::default()).unwrap().domains)
+    }
+
+    fn thermometer(cells: &[&str]) -> api::Constraint {
+        api::Constraint { r#type: api::ConstraintType::Thermometer, cells: cells.iter().map(|cell| cell.to_string()).collect() }
+    }
+
+    #[test]
+    fn test_transform() {
+        // A quarter turn clockwise: the first column, bottom up, becomes the first row
+        let rotation = Transform { transpose: true, rows: IDENTITY, cols: REVERSED, digits: DIGITS };
+        let rotated = transform(LINE, &rotation);
+        assert_eq!(&rotated[..9], ".3...7..4");
+        assert_eq!(transform(&rotated, &rotation.inverse()), LINE);
+
+        let shuffled = Transform { transpose: true, rows: orders()[700], cols: orders()[123], digits: [0, 3, 1, 4, 5, 9, 2, 6, 8, 7] };
+        assert_eq!(transform(&transform(LINE, &shuffled), &shuffled.inverse()), LINE);
+    }
+
+    #[test]
+    fn test_canonical() {
+        let expected = canonical_line(LINE);
+        assert_eq!(canonical_line(&expected), expected);
+        let givens = |line: &str| line.chars().filter(|ch| *ch != '.').count();
+        assert_eq!(givens(&expected), givens(LINE));
+
+        let transforms = [
+            Transform { transpose: false, rows: REVERSED, cols: IDENTITY, digits: DIGITS },
+            Transform { transpose: true, rows: orders()[1000], cols: orders()[77], digits: COMPLEMENT },
+            Transform { transpose: false, rows: orders()[5], cols: orders()[1295], digits: [0, 2, 3, 4, 5, 6, 7, 8, 9, 1] },
+        ];
+        for transform in transforms.iter() {
+            assert_eq!(canonical_line(&self::transform(LINE, transform)), expected);
+        }
+
+        // A different puzzle
+        assert_ne!(canonical_line(&LINE.replacen('4', ".", 1)), expected);
+
+        // Lots of blank lines, which are all alike
+        assert_eq!(canonical_line(&".".repeat(81)), ".".repeat(81));
+        let one = format!("{}5{}", ".".repeat(40), ".".repeat(40));
+        assert_eq!(canonical_line(&one), format!("{}1", ".".repeat(80)));
+    }
+
+    #[test]
+    fn test_canonical_variant() {
+        let domains = text::parse(LINE).unwrap();
+        let constraints = api::Constraints {
+            globals: api::GlobalConstraints { anti_knight: true, anti_king: false },
+            locals: vec![thermometer(&["3:1", "3:2", "2:1"])],
+        };
+        let expected = canonical(&domains, &constraints).unwrap();
+
+        // Turned upside down, with the digits complemented (so the thermometer runs the other way)
+        let flip = Transform { transpose: false, rows: REVERSED, cols: IDENTITY, digits: COMPLEMENT };
+        let flipped = canonical(&flip.domains(&domains), &flip.constraints(&constraints)).unwrap();
+        assert_eq!(flipped.key(), expected.key());
+        assert_eq!(flipped.constraints.locals[0].cells, expected.constraints.locals[0].cells);
+
+        // Swapping rows within a band would break up the thermometer (and change which cells are a knight's
+        // move apart), so it makes a different puzzle
+        let swap = Transform { transpose: false, rows: [1, 0, 2, 3, 4, 5, 6, 7, 8], cols: IDENTITY, digits: DIGITS };
+        let swapped = canonical(&swap.domains(&domains), &constraints).unwrap();
+        assert_ne!(swapped.domains, expected.domains);
+
+        // Other relabellings would change what the thermometer means
+        let relabelled = Transform { transpose: false, rows: IDENTITY, cols: IDENTITY, digits: [0, 2, 1, 3, 4, 5, 6, 7, 8, 9] };
+        let relabelled = canonical(&relabelled.domains(&domains), &relabelled.constraints(&constraints)).unwrap();
+        assert_ne!(relabelled.domains, expected.domains);
+    }
+
+}
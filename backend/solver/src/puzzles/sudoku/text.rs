@@ -0,0 +1,200 @@
+// Plain text sudoku formats.
+//
+// A line holds all 81 cells in row-major order, e.g.
+//
+//   48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....
+//
+// and a grid holds 9 rows of 9 cells, optionally separated into boxes, e.g.
+//
+//   4 8 . | 3 . . | . . .
+//   . . . | . . . | . 7 1
+//   ------+-------+------
+//   ...
+//
+// Blank cells are '.' or '0'.
+
+use super::api;
+use crate::error::{Error, ErrorCode};
+
+
+fn cell_name(i: usize) -> String {
+    format!("{}:{}", i / 9 + 1, i % 9 + 1)
+}
+
+// Characters that only lay out a grid
+fn is_separator(ch: char) -> bool {
+    ch.is_whitespace() || matches!(ch, '|' | '-' | '+' | '=')
+}
+
+// The cells of a row (or of a whole puzzle), or None if the line is only layout
+fn cells(line: &str) -> Option<Vec<char>> {
+    if line.chars().all(is_separator) {
+        return None;
+    }
+    return Some(line.chars().filter(|ch| !ch.is_whitespace() && *ch != '|').collect());
+}
+
+// A grid that ends before its 9th row, whose last row is on `line`
+fn incomplete_grid(rows: &[char], line: usize) -> Error {
+    let message = format!("incomplete grid: expected 9 rows, got {} ending at line {}", rows.len() / 9, line);
+    return Error::new(ErrorCode::MalformedInput, message);
+}
+
+fn parse_cells(cells: &[char]) -> Result<api::Domains, Error> {
+    if cells.len() != 81 {
+        return Err(Error::invalid_puzzle(format!("expected 81 cells, got {}", cells.len())));
+    }
+    let mut domains = api::Domains::new();
+    for (i, cell) in cells.iter().enumerate() {
+        let domain = match cell {
+            '.' | '0' => (1..=9).collect(),
+            '1'..='9' => vec![cell.to_digit(10).unwrap() as usize],
+            _ => return Err(Error::invalid_puzzle(format!("unexpected character '{}'", cell)).with_cell(cell_name(i))),
+        };
+        domains.insert(cell_name(i), domain);
+    }
+    return Ok(domains);
+}
+
+// Parse a puzzle in either format
+pub fn parse(text: &str) -> Result<api::Domains, Error> {
+    let cells = text.lines().filter_map(cells).flatten().collect::<Vec<_>>();
+    return parse_cells(&cells);
+}
+
+// Parse every puzzle in a file of lines and/or grids, along with the (1-based) line each starts on.
+// Blank lines and lines starting with '#' are skipped.
+pub fn parse_all(text: &str) -> Vec<(usize, Result<api::Domains, Error>)> {
+    let mut puzzles = Vec::new();
+    let mut rows : Vec<char> = Vec::new();
+    let mut start = 0;
+    let mut last = 0;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let cells = match cells(line) {
+            Some(cells) => cells,
+            None => continue,
+        };
+        // Anything but another row ends a grid, and starts a puzzle of its own
+        if !rows.is_empty() && cells.len() != 9 {
+            puzzles.push((start, Err(incomplete_grid(&rows, last))));
+            rows.clear();
+        }
+        if rows.is_empty() {
+            start = i + 1;
+            if cells.len() != 9 {
+                puzzles.push((start, parse_cells(&cells)));
+                continue;
+            }
+        }
+        rows.extend(cells);
+        last = i + 1;
+        if rows.len() == 81 {
+            puzzles.push((start, parse_cells(&rows)));
+            rows.clear();
+        }
+    }
+    if !rows.is_empty() {
+        puzzles.push((start, Err(incomplete_grid(&rows, last))));
+    }
+    return puzzles;
+}
+
+fn format_cell(domains: &api::Domains, i: usize) -> char {
+    match domains.get(&cell_name(i)).map(|domain| domain.as_slice()) {
+        Some([digit]) if (1..=9).contains(digit) => char::from_digit(*digit as u32, 10).unwrap(),
+        _ => '.',
+    }
+}
+
+// All 81 cells on one line, with '.' for unsolved cells
+pub fn to_line(domains: &api::Domains) -> String {
+    (0..81).map(|i| format_cell(domains, i)).collect()
+}
+
+// 9 rows of 9 cells, separated into boxes, with '.' for unsolved cells
+pub fn to_grid(domains: &api::Domains) -> String {
+    let mut grid = String::new();
+    for r in 0..9 {
+        if r == 3 || r == 6 {
+            grid.push_str("------+-------+------\n");
+        }
+        for c in 0..9 {
+            if c == 3 || c == 6 {
+                grid.push_str("| ");
+            }
+            grid.push(format_cell(domains, r*9 + c));
+            grid.push(if c == 8 { '\n' } else { ' ' });
+        }
+    }
+    return grid;
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const LINE : &str = "48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....";
+
+    #[test]
+    fn test_line_round_trip() {
+        let domains = parse(LINE).unwrap();
+        assert_eq!(domains["1:2"], vec![8]);
+        assert_eq!(domains["1:3"].len(), 9);
+        assert_eq!(to_line(&domains), LINE);
+
+        assert_eq!(parse(&LINE.replace('.', "0")).unwrap(), domains);
+        assert!(parse(&LINE[1..]).is_err());
+        assert_eq!(parse(&LINE.replacen('.', "x", 1)).unwrap_err().cell, Some("1:3".to_string()));
+    }
+
+    #[test]
+    fn test_grid_round_trip() {
+        let domains = parse(LINE).unwrap();
+        let grid = to_grid(&domains);
+        assert!(grid.starts_with("4 8 . | 3 . . | . . .\n"));
+        assert_eq!(parse(&grid).unwrap(), domains);
+        assert_eq!(parse(&grid.replace(['|', '-', '+', ' '], "")).unwrap(), domains);
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let domains = parse(LINE).unwrap();
+        let text = format!("# puzzles\n{}\n\n{}\n{}\n12345\n", LINE, to_grid(&domains), LINE);
+        let puzzles = parse_all(&text);
+        assert_eq!(puzzles.len(), 4);
+        assert_eq!(puzzles[0].0, 2);
+        assert_eq!(puzzles[0].1.as_ref().unwrap(), &domains);
+        assert_eq!(puzzles[1].0, 4);
+        assert_eq!(puzzles[1].1.as_ref().unwrap(), &domains);
+        assert_eq!(puzzles[2].0, 16);
+        assert!(puzzles[3].1.is_err());
+    }
+
+    #[test]
+    fn test_parse_all_incomplete_grid() {
+        let domains = parse(LINE).unwrap();
+        let grid = to_grid(&domains);
+        // The first 5 rows of a grid, then a line, then the last 2 rows of a grid
+        let partial = grid.lines().take(6).collect::<Vec<_>>().join("\n");
+        let text = format!("{}\n{}\n{}", partial, LINE, grid.lines().skip(9).collect::<Vec<_>>().join("\n"));
+        let puzzles = parse_all(&text);
+        assert_eq!(puzzles.len(), 3);
+
+        let error = puzzles[0].1.as_ref().unwrap_err();
+        assert_eq!(puzzles[0].0, 1);
+        assert_eq!(error.code, ErrorCode::MalformedInput);
+        assert!(error.message.contains("got 5 ending at line 6"), "{}", error.message);
+
+        assert_eq!(puzzles[1].0, 7);
+        assert_eq!(puzzles[1].1.as_ref().unwrap(), &domains);
+
+        assert_eq!(puzzles[2].0, 8);
+        assert!(puzzles[2].1.as_ref().unwrap_err().message.contains("got 2 ending at line 9"));
+    }
+
+}
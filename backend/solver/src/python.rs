@@ -0,0 +1,133 @@
+// Python bindings, so that Python can solve in-process instead of through the server. Built with the
+// `python` feature as an extension module named `solver`, e.g. with
+//
+//   maturin develop --features python
+//
+// or with `PYO3_BUILD_EXTENSION_MODULE=1 cargo build --release --features python`, then copying
+// target/release/libsolver.so to solver.so somewhere on the Python path.
+//
+// `solve` takes and returns the same dicts as the server's /solve (the `type` can be left out for a
+// sudoku). It can report the progress and breadcrumbs /solve_stream sends as events, to callbacks:
+//
+//   solver.solve(input, on_progress=lambda progress: ..., on_breadcrumb=print)
+//
+// where `on_progress` gets {"domains": ..., "stats": ...} and stops the solve by returning False. Errors
+// are raised as `solver.SolverError`, with the error's `code`, `field` and `cell` as attributes.
+//
+// As with the server, there's no time limit unless the input's config sets one or `timeout_ms` is given,
+// which bounds it the way the server's --timeout-ms does.
+
+use crate::api;
+use crate::error::{Error, ErrorCode};
+use crate::solver::{Observer, Stats};
+use crate::types::{Domains, Reporter};
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+
+create_exception!(solver, SolverError, PyException);
+
+fn to_python_error(py: Python<'_>, error: Error) -> PyErr {
+    let err = SolverError::new_err(error.to_string());
+    let value = err.value(py);
+    // Setting attributes on a fresh exception can't fail
+    value.setattr("code", error.code.name()).unwrap();
+    value.setattr("field", error.field).unwrap();
+    value.setattr("cell", error.cell).unwrap();
+    return err;
+}
+
+fn to_json(py: Python<'_>, value: &Bound<'_, PyAny>) -> PyResult<String> {
+    return py.import("json")?.call_method1("dumps", (value,))?.extract();
+}
+
+fn from_json<'py>(py: Python<'py>, value: &serde_json::Value) -> PyResult<Bound<'py, PyAny>> {
+    return py.import("json")?.call_method1("loads", (value.to_string(),));
+}
+
+// Passes progress and breadcrumbs on to the callbacks, holding on to the first exception one raises
+struct Callbacks {
+    on_progress: Option<Py<PyAny>>,
+    on_breadcrumb: Option<Py<PyAny>>,
+    stopped: AtomicBool,
+    error: Arc<Mutex<Option<PyErr>>>,
+}
+
+impl Callbacks {
+
+    fn call(&self, callback: &Option<Py<PyAny>>, value: serde_json::Value) {
+        let Some(callback) = callback else { return };
+        Python::attach(|py| {
+            let result = from_json(py, &value).and_then(|value| callback.call1(py, (value,)));
+            match result {
+                // Only an explicit False stops, not the None of a callback that returns nothing
+                Ok(result) => if matches!(result.extract::<bool>(py), Ok(false)) {
+                    self.stopped.store(true, Ordering::SeqCst);
+                },
+                Err(err) => {
+                    self.error.lock().unwrap().get_or_insert(err);
+                    self.stopped.store(true, Ordering::SeqCst);
+                },
+            }
+        });
+    }
+
+}
+
+impl Observer for Callbacks {
+
+    // To stderr without a callback, as when solving without an observer
+    fn breadcrumb(&self, breadcrumb: String) {
+        match &self.on_breadcrumb {
+            Some(_) => self.call(&self.on_breadcrumb, serde_json::Value::from(breadcrumb)),
+            None => eprintln!("{}", breadcrumb),
+        }
+    }
+
+    fn progress(&self, reporter: &dyn Reporter, domains: &Domains, stats: &Stats) {
+        if self.on_progress.is_none() {
+            return;
+        }
+        let domains = domains.iter().enumerate()
+            .map(|(id, domain)| (reporter.variable_name(id).clone(), serde_json::Value::from(domain.iter().collect::<Vec<_>>())))
+            .collect::<serde_json::Map<_, _>>();
+        self.call(&self.on_progress, serde_json::json!({ "domains": domains, "stats": stats }));
+    }
+
+    fn cancelled(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+}
+
+#[pyfunction]
+#[pyo3(signature = (input, on_progress=None, on_breadcrumb=None, timeout_ms=None))]
+fn solve<'py>(py: Python<'py>, input: &Bound<'py, PyAny>, on_progress: Option<Py<PyAny>>, on_breadcrumb: Option<Py<PyAny>>, timeout_ms: Option<u64>) -> PyResult<Bound<'py, PyAny>> {
+    let value : serde_json::Value = serde_json::from_str(&to_json(py, input)?)
+        .map_err(|err| to_python_error(py, Error::new(ErrorCode::MalformedInput, err.to_string())))?;
+    let mut input = api::parse_in_process(value).map_err(|error| to_python_error(py, error))?;
+    input.limit(timeout_ms);
+
+    let error = Arc::new(Mutex::new(None));
+    let callbacks = Callbacks { on_progress, on_breadcrumb, stopped: AtomicBool::new(false), error: error.clone() };
+    // Let other Python threads run while solving; the callbacks take the interpreter back when they're called
+    let output = py.detach(|| api::solve_observed(input, Box::new(callbacks)));
+    if let Some(err) = error.lock().unwrap().take() {
+        return Err(err);
+    }
+    let output = output.map_err(|error| to_python_error(py, error))?;
+    // Outputs only hold strings, numbers and the like, which always serialize
+    return from_json(py, &serde_json::to_value(output).unwrap());
+}
+
+#[pymodule]
+fn solver(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(solve, m)?)?;
+    m.add("SolverError", m.py().get_type::<SolverError>())?;
+    m.add("API_VERSION", api::API_VERSION)?;
+    return Ok(());
+}
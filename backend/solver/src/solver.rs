@@ -1,28 +1,113 @@
 use crate::types::*;
 use crate::constraint::*;
+use crate::branching::*;
 
 use serde::Serialize;
 use serde::Deserialize;
+use schemars::JsonSchema;
+use std::collections::BTreeMap;
+use std::time::Duration;
 
+// std's Instant panics on wasm32-unknown-unknown, which has no clock of its own
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::Instant;
 
-#[derive(Serialize, Debug)]
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum SolveResult {
     Unsolvable,
     Solved,
     Stuck,
+    // A time or node limit was hit, or the solve was cancelled, before the solver finished
+    Timeout,
+}
+
+impl SolveResult {
+
+    // Name as it appears in the JSON
+    pub fn name(&self) -> &'static str {
+        match self {
+            SolveResult::Unsolvable => "unsolvable",
+            SolveResult::Solved     => "solved",
+            SolveResult::Stuck      => "stuck",
+            SolveResult::Timeout    => "timeout",
+        }
+    }
+
 }
 
-#[derive(Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default)]
 pub struct Config {
     pub greedy: bool,
     pub breadcrumbs: bool,
+    #[serde(default)]
+    pub branching: Branching,
+    #[serde(default)]
+    pub value_ordering: ValueOrdering,
+    // Seed for random branching and value ordering
+    #[serde(default)]
+    pub seed: u64,
+    // Give up after this long, keeping the deductions made so far
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    // Give up after this many guesses, keeping the deductions made so far
+    #[serde(default)]
+    pub max_nodes: Option<u64>,
+}
+
+// Doc comments, so that the output schema describes each field
+#[derive(Serialize, JsonSchema, Debug, Clone, Default)]
+pub struct Stats {
+    /// Passes over the live constraints
+    pub propagation_passes: u64,
+    /// Calls to each kind of constraint
+    pub invocations: BTreeMap<&'static str, u64>,
+    /// Constraints replaced by smaller constraints
+    pub rewrites: u64,
+    /// Values tried for a cell
+    pub guesses: u64,
+    /// Propagations (after a guess or otherwise) that ended in a contradiction
+    pub contradictions: u64,
+    /// Deepest nesting of guesses: at most 1 when solving, which tries each guess on its own, and deeper when
+    /// counting solutions
+    pub max_depth: usize,
+    /// Rounds of guessing that ruled values out, each guessing again with what the last one deduced; only when
+    /// solving
+    pub guessing_rounds: usize,
+    /// Most constraints live at once
+    pub peak_constraints: usize,
+}
+
+// Result of an exhaustive search
+#[derive(Debug, Clone, Default)]
+pub struct Solutions {
+    // Solutions found, stopping at the requested limit
+    pub count: usize,
+    // The first solution found
+    pub first: Option<Vec<Domain>>,
+    // A time or node limit was hit before the search finished, so there may be more solutions
+    pub timeout: bool,
+}
+
+// Follows a solve as it runs, e.g. to stream its progress to a client
+pub trait Observer {
+    // A deduction or guess, when breadcrumbs are enabled (instead of writing it to stderr)
+    fn breadcrumb(&self, breadcrumb: String);
+    // The domains after each round of deductions, and the work done so far.
+    // The reporter names the variables.
+    fn progress(&self, reporter: &dyn Reporter, domains: &Domains, stats: &Stats);
+    // Whether to stop as soon as possible, keeping the deductions made so far
+    fn cancelled(&self) -> bool;
 }
 
 pub struct Solver {
     pub variable_names: Vec<String>,
     pub constraint_names: Vec<String>,
     pub config: Config,
+    pub observer: Option<Box<dyn Observer>>,
 }
 
 impl Reporter for Solver {
@@ -36,7 +121,10 @@ impl Reporter for Solver {
     }
 
     fn emit(&self, breadcrumb: String) {
-        eprint!("{}\n", breadcrumb);
+        match &self.observer {
+            Some(observer) => observer.breadcrumb(breadcrumb),
+            None => eprintln!("{}", breadcrumb),
+        }
     }
 
     fn enabled(&self) -> bool {
@@ -45,59 +133,239 @@ impl Reporter for Solver {
 
 }
 
-// TODO branch...
+// Undo log for constraint rewrites made while exploring a guess.
+// Together with the trail kept by Domains, this lets a guess be explored in place and rolled back.
+enum Change {
+    Removed(usize, Box<dyn Constraint>),
+    Added,
+}
+
+#[derive(Default)]
+struct Trail {
+    changes: Vec<Change>,
+    checkpoints: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Checkpoint {
+    domains: usize,
+    constraints: usize,
+}
+
+impl Trail {
+
+    fn checkpoint(&mut self, domains: &mut Domains) -> Checkpoint {
+        self.checkpoints += 1;
+        return Checkpoint {
+            domains: domains.checkpoint(),
+            constraints: self.changes.len(),
+        };
+    }
+
+    fn rollback(&mut self, checkpoint: Checkpoint, domains: &mut Domains, constraints: &mut Constraints) {
+        while self.changes.len() > checkpoint.constraints {
+            match self.changes.pop().unwrap() {
+                Change::Added => {
+                    constraints.pop();
+                },
+                Change::Removed(i, constraint) => {
+                    // Invert swap_remove
+                    constraints.push(constraint);
+                    let last = constraints.len() - 1;
+                    constraints.swap(i, last);
+                },
+            }
+        }
+        self.checkpoints -= 1;
+        domains.rollback(checkpoint.domains);
+    }
+
+    fn commit(&mut self, checkpoint: Checkpoint, domains: &mut Domains) {
+        self.checkpoints -= 1;
+        if self.checkpoints == 0 {
+            self.changes.clear();
+        }
+        domains.commit(checkpoint.domains);
+    }
+
+    fn remove(&mut self, constraints: &mut Constraints, i: usize) {
+        let constraint = constraints.swap_remove(i);
+        if self.checkpoints > 0 {
+            self.changes.push(Change::Removed(i, constraint));
+        }
+    }
+
+    fn add(&mut self, constraints: &mut Constraints, new_constraints: Constraints) {
+        for constraint in new_constraints {
+            constraints.push(constraint);
+            if self.checkpoints > 0 {
+                self.changes.push(Change::Added);
+            }
+        }
+    }
+}
+
+// Mutable state of a single solve
+struct Search {
+    trail: Trail,
+    brancher: Brancher,
+    deadline: Option<Instant>,
+    stats: Stats,
+    // calls to each kind of constraint, indexed by ConstraintKind and only named in stats()
+    invocations: [u64; ConstraintKind::ALL.len()],
+}
+
+impl Search {
+
+    fn new(config: &Config, constraint_count: usize) -> Self {
+        Search {
+            trail: Trail::default(),
+            brancher: Brancher::new(config.branching, config.value_ordering, config.seed, constraint_count),
+            deadline: config.timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms)),
+            stats: Stats::default(),
+            invocations: [0; ConstraintKind::ALL.len()],
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        for kind in ConstraintKind::ALL {
+            let count = self.invocations[kind as usize];
+            if count > 0 {
+                stats.invocations.insert(kind.name(), count);
+            }
+        }
+        return stats;
+    }
+
+    fn limit_reached(&self, config: &Config) -> bool {
+        if let Some(max_nodes) = config.max_nodes {
+            if self.stats.guesses >= max_nodes {
+                return true;
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return true;
+            }
+        }
+        return false;
+    }
+}
 
 impl Solver {
 
-    pub fn solve(&self, domains: &mut Domains, constraints: &mut Constraints) -> SolveResult {
-        let result = self.simplify(domains, constraints);
+    fn stop(&self, search: &Search) -> bool {
+        return search.limit_reached(&self.config) || self.observer.as_ref().is_some_and(|observer| observer.cancelled());
+    }
+
+    pub fn solve(&self, domains: &mut Domains, constraints: &mut Constraints) -> (SolveResult, Stats) {
+        let mut search = Search::new(&self.config, self.constraint_names.len());
+        let result = self.search(domains, constraints, &mut search, 0);
+        return (result, search.stats());
+    }
+
+    // Count solutions by guessing recursively, up to `limit`.
+    // Unlike `solve` this is exhaustive, so it can tell a unique solution from one too hard to deduce.
+    // Greedy and breadcrumbs are ignored.
+    pub fn count_solutions(&self, domains: &mut Domains, constraints: &mut Constraints, limit: usize) -> (Solutions, Stats) {
+        let mut search = Search::new(&self.config, self.constraint_names.len());
+        let mut solutions = Solutions::default();
+        if limit > 0 {
+            self.enumerate(domains, constraints, &mut search, 0, limit, &mut solutions);
+        }
+        return (solutions, search.stats());
+    }
+
+    // Returns whether to stop searching
+    fn enumerate(&self, domains: &mut Domains, constraints: &mut Constraints, search: &mut Search, depth: usize, limit: usize, solutions: &mut Solutions) -> bool {
+        search.stats.max_depth = search.stats.max_depth.max(depth);
+        match self.simplify(domains, constraints, search) {
+            SolveResult::Solved => {
+                solutions.count += 1;
+                if solutions.first.is_none() {
+                    solutions.first = Some(domains.to_vec());
+                }
+                return solutions.count >= limit;
+            },
+            SolveResult::Stuck => {
+                let variable = match search.brancher.order_variables(domains, constraints).first() {
+                    Some(variable) => *variable,
+                    None => return false,
+                };
+                for value in search.brancher.order_values(domains[variable]) {
+                    if self.stop(search) {
+                        solutions.timeout = true;
+                        return true;
+                    }
+                    search.stats.guesses += 1;
+                    let checkpoint = search.trail.checkpoint(domains);
+                    domains[variable] = Domain::single(value);
+                    let stop = self.enumerate(domains, constraints, search, depth + 1, limit, solutions);
+                    search.trail.rollback(checkpoint, domains, constraints);
+                    if stop {
+                        return true;
+                    }
+                }
+                return false;
+            },
+            _ => return false,
+        }
+    }
+
+    fn search(&self, domains: &mut Domains, constraints: &mut Constraints, search: &mut Search, round: usize) -> SolveResult {
+        search.stats.guessing_rounds = search.stats.guessing_rounds.max(round);
+        let result = self.simplify(domains, constraints, search);
+        if let Some(observer) = &self.observer {
+            observer.progress(self, domains, &search.stats());
+        }
         match result {
             SolveResult::Stuck => {
 
-                // Heuristic = variable with smallest domain
-                // TODO use something smarter here, e.g. most constrained variables
-                let mut variables = domains.iter().enumerate()
-                    .filter(|(_, domain)| domain.len() > 1)
-                    .collect::<Vec<_>>();
-                variables
-                    .sort_by(|(_, d1), (_, d2)| d1.len().cmp(&d2.len()));
-                let mut variables = variables.iter()
-                    .map(|(v, _)| *v)
-                    .collect::<Vec<_>>();
-
-                // Sort again, looking at most constrained
-                let num_constraints = |v| constraints.iter().filter(|c| c.variables().contains(v)).count();
-                variables.sort_by(|v1, v2| num_constraints(*v2).cmp(&num_constraints(*v1)));
+                let variables = search.brancher.order_variables(domains, constraints);
 
                 for variable in variables.iter() {
                     let domain = domains[*variable];
                     let mut inferred_domain : Domain = domain;
-                    for value in domain.iter() {
+                    for value in search.brancher.order_values(domain) {
+                        if self.stop(search) {
+                            // Values ruled out so far are still sound deductions
+                            domains[*variable] = inferred_domain;
+                            return SolveResult::Timeout;
+                        }
+                        search.stats.guesses += 1;
+                        search.stats.max_depth = 1;
                         // Guess variable = value and try to solve without branching
-                        let mut branch_domains = domains.clone();
-                        let mut branch_constraints = constraints.clone();
-                        branch_domains[*variable] = Domain::single(value);
+                        let before = if self.config.branching == Branching::Impact { search_space(domains) } else { 0.0 };
+                        let checkpoint = search.trail.checkpoint(domains);
+                        domains[*variable] = Domain::single(value);
                         if self.config.breadcrumbs {
                             self.emit(format!("guess {} = {}", self.variable_name(*variable), value));
                         }
-                        // TODO copy !!!
-                        let result = self.simplify(&mut branch_domains, &mut branch_constraints);
+                        let result = self.simplify(domains, constraints, search);
+                        if self.config.branching == Branching::Impact {
+                            let after = match result {
+                                SolveResult::Unsolvable => None,
+                                _ => Some(search_space(domains)),
+                            };
+                            search.brancher.record_impact(*variable, value, before, after);
+                        }
                         match result {
                             SolveResult::Unsolvable => { inferred_domain.remove(value); },
-                            SolveResult::Solved => if self.config.greedy {
-                                *domains = branch_domains;
-                                *constraints = branch_constraints;
+                            SolveResult::Solved if self.config.greedy => {
+                                search.trail.commit(checkpoint, domains);
                                 return SolveResult::Solved;
-                            } else {},
+                            },
                             _ => {},
                         }
+                        search.trail.rollback(checkpoint, domains, constraints);
                     }
                     if inferred_domain != domain {
                         if self.config.breadcrumbs {
                             self.emit(format!("{} is {} by guessing", self.variable_name(*variable), inferred_domain));
                         }
                         domains[*variable] = inferred_domain;
-                        return self.solve(domains, constraints);
+                        return self.search(domains, constraints, search, round + 1);
                     }
                 }
 
@@ -111,30 +379,39 @@ impl Solver {
     // only solved constraints
     // order of constraints
     // only constraints with dirty variables
-    fn simplify(&self, domains: &mut Domains, constraints: &mut Constraints) -> SolveResult {
+    fn simplify(&self, domains: &mut Domains, constraints: &mut Constraints, search: &mut Search) -> SolveResult {
+        let trail = &mut search.trail;
+        let stats = &mut search.stats;
         loop {
+            stats.propagation_passes += 1;
+            stats.peak_constraints = stats.peak_constraints.max(constraints.len());
             let mut any_progress = false;
             let mut i = 0;
             while i < constraints.len() {
+                search.invocations[constraints[i].kind() as usize] += 1;
                 let result = constraints[i].check_and_simplify(domains, self);
                 match result {
                     SimplifyResult::Unsolvable => {
+                        stats.contradictions += 1;
+                        search.brancher.record_failure(constraints[i].id());
                         return SolveResult::Unsolvable;
                     },
                     SimplifyResult::Solved => {
-                        constraints.swap_remove(i);
+                        trail.remove(constraints, i);
                         any_progress = true;
                     },
                     SimplifyResult::Stuck => {
-                        i = i + 1;
+                        i += 1;
                     },
                     SimplifyResult::Progress => {
-                        i = i + 1;
+                        i += 1;
                         any_progress = true;
                     },
-                    SimplifyResult::Rewrite(mut sub_constraints)  => {
-                        constraints.swap_remove(i);
-                        constraints.append(&mut sub_constraints);
+                    SimplifyResult::Rewrite(sub_constraints)  => {
+                        stats.rewrites += 1;
+                        trail.remove(constraints, i);
+                        trail.add(constraints, sub_constraints);
+                        stats.peak_constraints = stats.peak_constraints.max(constraints.len());
                         any_progress = true;
                     }
                 }
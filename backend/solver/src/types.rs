@@ -1,16 +1,119 @@
 use crate::bit_set::*;
 
+use std::ops::{Deref, Index, IndexMut};
+
 
 pub type Domain = BitSet;
-pub type Domains = Vec<Domain>;
 
 pub type Variable = usize;
 pub type VariableSet = BitSet;
 pub type ConstraintID = usize;
 
+// The domain of every variable.
+//
+// While a checkpoint is open, every write through `IndexMut` records the previous domain on a trail,
+// so the solver can explore a guess in place and then roll back instead of copying all the domains.
+#[derive(Clone,Debug,Default)]
+pub struct Domains {
+    domains: Vec<Domain>,
+    trail: Vec<(Variable, Domain)>,
+    checkpoints: usize,
+}
+
+impl Domains {
+
+    pub fn new() -> Self {
+        Domains::default()
+    }
+
+    pub fn push(&mut self, domain: Domain) {
+        self.domains.push(domain);
+    }
+
+    pub fn checkpoint(&mut self) -> usize {
+        self.checkpoints += 1;
+        return self.trail.len();
+    }
+
+    // Undo every write made since the checkpoint.
+    pub fn rollback(&mut self, checkpoint: usize) {
+        while self.trail.len() > checkpoint {
+            let (variable, domain) = self.trail.pop().unwrap();
+            self.domains[variable] = domain;
+        }
+        self.checkpoints -= 1;
+    }
+
+    // Keep every write made since the checkpoint.
+    // The trail is still needed if an outer checkpoint is open.
+    pub fn commit(&mut self, checkpoint: usize) {
+        self.checkpoints -= 1;
+        if self.checkpoints == 0 {
+            self.trail.truncate(0);
+        } else {
+            debug_assert!(self.trail.len() >= checkpoint);
+        }
+    }
+
+}
+
+impl Deref for Domains {
+    type Target = [Domain];
+
+    fn deref(&self) -> &[Domain] {
+        &self.domains
+    }
+}
+
+impl Index<Variable> for Domains {
+    type Output = Domain;
+
+    fn index(&self, variable: Variable) -> &Domain {
+        &self.domains[variable]
+    }
+}
+
+impl IndexMut<Variable> for Domains {
+
+    fn index_mut(&mut self, variable: Variable) -> &mut Domain {
+        if self.checkpoints > 0 {
+            self.trail.push((variable, self.domains[variable]));
+        }
+        &mut self.domains[variable]
+    }
+}
+
 pub trait Reporter {
     fn variable_name(&self, variable: Variable) -> &String;
     fn constraint_name(&self, id: ConstraintID) -> &String;
     fn emit(&self, breadcrumb: String);
     fn enabled(&self) -> bool;
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_rollback() {
+        let mut domains = Domains::new();
+        domains.push(Domain::range(1, 9));
+        domains.push(Domain::range(1, 9));
+
+        domains[0].remove(5);
+
+        let outer = domains.checkpoint();
+        domains[0] = Domain::single(1);
+        let inner = domains.checkpoint();
+        domains[1].remove(1);
+        domains[1].remove(2);
+        domains.commit(inner);
+        assert_eq!(domains[1], Domain::range(3, 9));
+        domains.rollback(outer);
+
+        assert_eq!(domains[0], Domain::range(1, 9).difference(Domain::single(5)));
+        assert_eq!(domains[1], Domain::range(1, 9));
+    }
+
+}
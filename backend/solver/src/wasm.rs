@@ -0,0 +1,99 @@
+// JavaScript bindings, so that a page can solve in the browser instead of through the server. Built with the
+// `wasm` feature and without the server's, e.g. with
+//
+//   cargo build --release --lib --target wasm32-unknown-unknown --no-default-features --features wasm
+//   wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/solver.wasm
+//
+// `solve` takes and returns the same JSON as the server's /solve, as strings (the `type` can be left out for
+// a sudoku). Errors are thrown as the JSON error body the server would respond with.
+//
+// Solving blocks the thread it runs on, so pages should call it from a worker. As with the server, there's
+// no time limit unless the input's config sets one or `timeout_ms` is given, which bounds it the way the
+// server's --timeout-ms does.
+
+use crate::api;
+use crate::error::{Error, ErrorCode};
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+
+fn solve_input(input: &str, timeout_ms: Option<u64>) -> Result<api::Output, Error> {
+    let value : serde_json::Value = serde_json::from_str(input)
+        .map_err(|err| Error::new(ErrorCode::MalformedInput, err.to_string()))?;
+    let mut input = api::parse_in_process(value)?;
+    input.limit(timeout_ms);
+    return api::solve(input);
+}
+
+// The output's JSON, or the error's
+fn solve_json(input: &str, timeout_ms: Option<u64>) -> Result<String, String> {
+    // Outputs and errors only hold strings, numbers and the like, which always serialize
+    return match solve_input(input, timeout_ms) {
+        Ok(output) => Ok(serde_json::to_string(&output).unwrap()),
+        Err(error) => Err(serde_json::to_string(&error).unwrap()),
+    };
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub fn solve(input: &str, timeout_ms: Option<u32>) -> Result<String, JsValue> {
+    return solve_json(input, timeout_ms.map(u64::from)).map_err(|error| JsValue::from_str(&error));
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub fn api_version() -> u32 {
+    return api::API_VERSION as u32;
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::{json, Value};
+
+    const LINE : &str = "48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5....";
+
+    fn input() -> Value {
+        json!({
+            "domains": crate::puzzles::sudoku::text::parse(LINE).unwrap(),
+            "constraints": { "globals": { "anti_knight": false, "anti_king": false }, "locals": [] },
+            "config": { "greedy": false, "breadcrumbs": false },
+        })
+    }
+
+    #[test]
+    fn test_solve_json() {
+        // The type defaults to sudoku
+        let output : Value = serde_json::from_str(&solve_json(&input().to_string(), None).unwrap()).unwrap();
+        assert_eq!(output["type"], "sudoku");
+        assert_eq!(output["result"], "solved");
+        assert_eq!(output["domains"]["1:3"], json!([7]));
+
+        let mut typed = input();
+        typed["type"] = json!("sudoku");
+        let again : Value = serde_json::from_str(&solve_json(&typed.to_string(), None).unwrap()).unwrap();
+        assert_eq!(again["domains"], output["domains"]);
+    }
+
+    #[test]
+    fn test_solve_json_errors() {
+        let error : Value = serde_json::from_str(&solve_json("not json", None).unwrap_err()).unwrap();
+        assert_eq!(error["code"], "malformed_input");
+        assert!(error["message"].is_string());
+
+        let mut unknown = input();
+        unknown["type"] = json!("kakuro");
+        let error : Value = serde_json::from_str(&solve_json(&unknown.to_string(), None).unwrap_err()).unwrap();
+        assert_eq!(error["code"], "unsupported");
+        assert_eq!(error["field"], "type");
+    }
+
+    #[test]
+    fn test_solve_json_timeout() {
+        let output : Value = serde_json::from_str(&solve_json(&input().to_string(), Some(0)).unwrap()).unwrap();
+        assert_eq!(output["result"], "timeout");
+    }
+
+}
@@ -0,0 +1,56 @@
+"""
+Converts pickled .sudoku files from older versions of the GUI to puzzle files:
+
+    convert_pickles.py sudokus/*.sudoku
+
+writes sudokus/<name>.json next to each one.
+"""
+import argparse
+import os
+import pickle
+
+from sudoku import *
+
+
+class Unpickler(pickle.Unpickler):
+    # classes that have since been renamed
+    RENAMED = {
+        "LineType": Line.Type,
+    }
+
+    def find_class(self, module, name):
+        if module == "sudoku" and name in self.RENAMED:
+            return self.RENAMED[name]
+        return super().find_class(module, name)
+
+
+def load_pickle(filename):
+    with open(filename, 'rb') as sudoku_file:
+        (board, constraints) = Unpickler(sudoku_file).load()
+    # pickles from before a constraint type was added don't have its field
+    for name, field in Constraints.__dataclass_fields__.items():
+        try:
+            getattr(constraints, name)
+        except AttributeError:
+            setattr(constraints, name, field.default_factory())
+    # and the oldest hold digits (or None) instead of Digits
+    for r, row in enumerate(board):
+        for c, digit in enumerate(row):
+            if not isinstance(digit, Digit):
+                board[r][c] = Digit([digit]) if digit is not None else Digit.blank()
+    # rebuild the constraints as the current classes would make them (sets, canonical lines, thermometers directed)
+    return (board, Constraints.decode(constraints.encode()))
+
+
+def main():
+    arg_parser = argparse.ArgumentParser()
+    arg_parser.add_argument("files", nargs="+", type=str)
+    for filename in arg_parser.parse_args().files:
+        (board, constraints) = load_pickle(filename)
+        (root, _) = os.path.splitext(filename)
+        write_puzzle_file(root + ".json", os.path.basename(root), board, constraints)
+        print(f"{filename} -> {root}.json")
+
+
+if __name__ == "__main__":
+    main()
@@ -1,6 +1,4 @@
 import json
-import pickle
-import json
 from http import HTTPStatus
 import lzstring
 import requests
@@ -8,6 +6,33 @@ from dataclasses import dataclass, field
 from typing import *
 from enum import Enum
 
+# Version of the solver's request format these requests are written in
+API_VERSION = 1
+
+# Seconds to wait for the solver to export a link before making one without it
+SHARE_TIMEOUT = 5
+
+# The solver's Python bindings (built with its `python` feature), used instead of the server when installed
+try:
+    import solver as _solver
+except ImportError:
+    _solver = None
+
+
+def _read_events(resp):
+    """(event, data) for each server-sent event in a streaming response"""
+    event = None
+    for line in resp.iter_lines(decode_unicode=True):
+        if line.startswith("event: "):
+            event = line[len("event: "):]
+        elif line.startswith("data: "):
+            yield (event, json.loads(line[len("data: "):]))
+
+
+def decode_cell(cell):
+    [r, c] = cell.split(':')
+    return (int(r) - 1, int(c) - 1)
+
 
 @dataclass(init=False,frozen=True)
 class Line:
@@ -139,11 +164,90 @@ class Constraints:
         return {
             'locals': constraints,
             'globals': {
-                "anti_king": self.anti_king,
                 "anti_knight": self.anti_knight,
+                "anti_king": self.anti_king,
             }
         }
 
+    @classmethod
+    def decode(cls, js):
+        constraints = cls()
+        for local in js.get('locals', []):
+            cells = [ decode_cell(cell) for cell in local['cells'] ]
+            match local['type']:
+                case "thermometer":
+                    constraints.thermometers.add(Line(cells, Line.Type.DIRECTED))
+                case "palindrome":
+                    constraints.palindromes.add(Line(cells, Line.Type.UNDIRECTED))
+                case "renban":
+                    constraints.renbans.add(Line(cells, Line.Type.UNDIRECTED))
+                case "whisper":
+                    constraints.whispers.add(Line(cells, Line.Type.UNDIRECTED))
+                case "white_kropki":
+                    constraints.kropkis.add(Kropki(Kropki.Color.WHITE, Edge(*cells)))
+                case "black_kropki":
+                    constraints.kropkis.add(Kropki(Kropki.Color.BLACK, Edge(*cells)))
+                case "x":
+                    constraints.xvs.add(XV(XV.Value.X, Edge(*cells)))
+                case "v":
+                    constraints.xvs.add(XV(XV.Value.V, Edge(*cells)))
+                case ty:
+                    raise ValueError(f"unknown constraint type {ty}")
+        constraints.anti_king = js.get('globals', {}).get('anti_king', False)
+        constraints.anti_knight = js.get('globals', {}).get('anti_knight', False)
+        return constraints
+
+
+# Puzzle files, as read and written by the solver (see puzzles/sudoku/file.rs)
+PUZZLE_FILE_VERSION = 1
+
+def _write_json(value, depth, column, out):
+    # Containers go on one line if they fit, otherwise one item per line
+    compact = json.dumps(value, separators=(',', ':'))
+    if column + len(compact) <= 100 or not isinstance(value, (list, dict)) or not value:
+        out.append(compact)
+        return
+    indent = "  " * (depth + 1)
+    items = list(value.items()) if isinstance(value, dict) else [ (None, item) for item in value ]
+    out.append('{' if isinstance(value, dict) else '[')
+    for i, (key, item) in enumerate(items):
+        out.append('\n' + indent)
+        column = len(indent)
+        if key is not None:
+            out.append(json.dumps(key) + ": ")
+            column += len(json.dumps(key)) + 2
+        _write_json(item, depth + 1, column, out)
+        if i + 1 < len(items):
+            out.append(',')
+    out.append('\n' + indent[2:])
+    out.append('}' if isinstance(value, dict) else ']')
+
+def write_puzzle_file(filename, title, board, constraints):
+    encoded = constraints.encode()
+    # sets iterate in a different order every run, so sort to keep files stable
+    encoded['locals'].sort(key=lambda local: (local['type'], local['cells']))
+    js = {
+        "version": PUZZLE_FILE_VERSION,
+        "title": title,
+        "domains": { f"{r+1}:{c+1}": board[r][c].values for r in range(9) for c in range(9) },
+        "constraints": { "globals": encoded['globals'], "locals": encoded['locals'] },
+    }
+    out = []
+    _write_json(js, 0, 0, out)
+    with open(filename, 'w') as puzzle_file:
+        puzzle_file.write("".join(out) + "\n")
+
+def read_puzzle_file(filename):
+    with open(filename) as puzzle_file:
+        js = json.load(puzzle_file)
+    if js.get("version") not in range(1, PUZZLE_FILE_VERSION + 1):
+        raise ValueError(f"{filename}: unsupported puzzle file version {js.get('version')}")
+    board = [ [ Digit.blank() for c in range(9) ] for r in range(9) ]
+    for cell, values in js["domains"].items():
+        (r, c) = decode_cell(cell)
+        board[r][c] = Digit(values)
+    return (board, Constraints.decode(js.get("constraints", {})))
+
 
 class Sudoku(object):
     """
@@ -151,7 +255,7 @@ class Sudoku(object):
     """
     def __init__(self, sudoku_name):
         self._sudoku_name = sudoku_name
-        self._sudoku_filename = f"sudokus/{sudoku_name}.sudoku"
+        self._sudoku_filename = f"sudokus/{sudoku_name}.json"
         self.reset()
 
     def rules(self):
@@ -174,7 +278,7 @@ class Sudoku(object):
             rules.append("Digits a king's move away can not be the same.")
         return " ".join(rules)
 
-    # TODO renban, kropkis, XV
+    # Used by to_url when the solver isn't running. TODO renban, kropkis, XV
     def to_json(self):
         js = {
             "title": self._sudoku_name,
@@ -212,28 +316,31 @@ class Sudoku(object):
         return json.dumps(js)
 
     def to_url(self):
-        return "https://app.crackingthecryptic.com/sudoku/?puzzleid=fpuzzles" + lzstring.LZString().compressToBase64(self.to_json())
+        """
+        (url, problem): a link to the puzzle, and why the solver couldn't export it if it's running but failed
+        to. The solver exports every constraint type, so it's preferred to to_json.
+        """
+        try:
+            resp = requests.post("http://localhost:3000/share", json=self.solver_input(False, False), timeout=SHARE_TIMEOUT)
+            if resp.status_code == HTTPStatus.OK:
+                return (resp.json()["url"], None)
+            problem = f"solver responded {resp.status_code}"
+        # Timeouts connecting are ConnectionErrors too, but mean the solver is there and stuck
+        except requests.exceptions.Timeout:
+            problem = f"solver didn't respond within {SHARE_TIMEOUT}s"
+        except requests.exceptions.ConnectionError:
+            problem = None
+        except requests.exceptions.RequestException as err:
+            problem = f"solver request failed ({err})"
+        url = "https://app.crackingthecryptic.com/sudoku/?puzzleid=fpuzzles" + lzstring.LZString().compressToBase64(self.to_json())
+        return (url, problem)
 
     def save(self):
-        with open(self._sudoku_filename, 'wb') as sudoku_file:
-            data = (self._board, self._constraints)
-            pickle.dump(data, sudoku_file)
+        write_puzzle_file(self._sudoku_filename, self._sudoku_name, self._board, self._constraints)
 
     def reset(self):
         try:
-            with open(self._sudoku_filename, 'rb') as sudoku_file:
-                (self._board, self._constraints) = pickle.load(sudoku_file)
-                # backwards compatabilty fixes
-                for name, field in Constraints.__dataclass_fields__.items():
-                    try:
-                        getattr(self._constraints, name)
-                    except AttributeError:
-                        setattr(self._constraints, name, field.default_factory())
-                for r, row in enumerate(self._board):
-                    for c, digit in enumerate(row):
-                        if not isinstance(digit, Digit):
-                            self._board[r][c] = Digit([digit]) if digit is not None else Digit.blank()
-
+            (self._board, self._constraints) = read_puzzle_file(self._sudoku_filename)
         except FileNotFoundError:
             self._board = [ [ Digit(list(range(1, 10))) for c in range(9) ] for r in range(9) ]
             self._constraints = Constraints()
@@ -248,15 +355,15 @@ class Sudoku(object):
     def constraints(self):
         return self._constraints
 
-    def solve(self, branch, greedy, trace):
-
+    def solver_input(self, greedy, trace):
         domains = {}
         for r in range(9):
             for c in range(9):
                 domains[f"{r+1}:{c+1}"] = self._board[r][c].values
 
-        solver_input = {
+        return {
             "type": "sudoku",
+            "api_version": API_VERSION,
             "domains": domains,
             "constraints": self._constraints.encode(),
             "config": {
@@ -265,19 +372,65 @@ class Sudoku(object):
             }
         }
 
-        resp = requests.post("http://localhost:3000/solve", json=solver_input)
-        if resp.status_code != HTTPStatus.OK:
-            return ("Error", None, None)
-
-        solver_output = resp.json()
-        result = solver_output["result"]
-        duration_ms = solver_output["duration_ms"]
+    def _solved_board(self, domains):
         board = [ [ self._board[r][c] for c in range(9) ] for r in range(9) ]
-        for variable, domain in solver_output["domains"].items():
-            [r, c] = variable.split(':')
-            r, c = int(r) - 1, int(c) - 1
+        for variable, domain in domains.items():
+            r, c = decode_cell(variable)
             board[r][c] = Digit(domain)
+        return board
 
-        breadcrumbs = [] # FIXME!
+    def _solved(self, output, breadcrumbs):
+        result = output["result"]
+        duration_ms = output["duration_ms"]
+        board = self._solved_board(output["domains"])
         return (f"{result.title()} ({duration_ms}ms)", board, breadcrumbs)
 
+    def _solve_in_process(self, solver_input, on_progress):
+        breadcrumbs = []
+        cancelled = False
+
+        def progress(data):
+            nonlocal cancelled
+            if on_progress is not None and on_progress(self._solved_board(data["domains"]), data["stats"]) is False:
+                cancelled = True
+                return False
+
+        try:
+            output = _solver.solve(solver_input, on_progress=progress, on_breadcrumb=breadcrumbs.append)
+        except _solver.SolverError:
+            return ("Error", None, "".join(f"{line}\n" for line in breadcrumbs))
+        breadcrumbs = "".join(f"{line}\n" for line in breadcrumbs)
+        if cancelled:
+            return ("Cancelled", None, breadcrumbs)
+        return self._solved(output, breadcrumbs)
+
+    def solve(self, branch, greedy, trace, on_progress=None):
+        """
+        Solve in-process if the solver's Python bindings are installed, otherwise using the server's event
+        stream. on_progress(board, stats) is called after each round of deductions, and cancels the solve by
+        returning False.
+        """
+        solver_input = self.solver_input(greedy, trace)
+        if _solver is not None:
+            return self._solve_in_process(solver_input, on_progress)
+        resp = requests.post("http://localhost:3000/solve_stream", json=solver_input, stream=True)
+        if resp.status_code == HTTPStatus.SERVICE_UNAVAILABLE:
+            return ("Busy, try again", None, None)
+        if resp.status_code != HTTPStatus.OK:
+            return ("Error", None, None)
+
+        breadcrumbs = ""
+        # Leaving early closes the connection, which stops the solver
+        with resp:
+            for (event, data) in _read_events(resp):
+                if event == "breadcrumb":
+                    breadcrumbs += data + "\n"
+                elif event == "progress" and on_progress is not None:
+                    if on_progress(self._solved_board(data["domains"]), data["stats"]) is False:
+                        return ("Cancelled", None, breadcrumbs)
+                elif event == "error":
+                    return ("Error", None, breadcrumbs)
+                elif event == "output":
+                    return self._solved(data, breadcrumbs)
+        return ("Error", None, breadcrumbs)
+
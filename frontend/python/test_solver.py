@@ -0,0 +1,61 @@
+# Smoke test for the solver's Python bindings, skipped unless the extension module is installed
+# (see backend/solver/src/python.rs). Run with
+#
+#   python -m unittest test_solver
+
+import unittest
+
+try:
+    import solver
+except ImportError:
+    solver = None
+
+
+LINE = "48.3............71.2.......7.5....6....2..8.............1.76...3.....4......5...."
+
+
+def solver_input():
+    domains = {}
+    for i, digit in enumerate(LINE):
+        domains[f"{i // 9 + 1}:{i % 9 + 1}"] = list(range(1, 10)) if digit == "." else [int(digit)]
+    return {
+        "api_version": solver.API_VERSION,
+        "domains": domains,
+        "constraints": {
+            "globals": {"anti_knight": False, "anti_king": False},
+            "locals": [],
+        },
+        "config": {"greedy": False, "breadcrumbs": False},
+    }
+
+
+@unittest.skipIf(solver is None, "solver module not installed")
+class SolverTest(unittest.TestCase):
+
+    def test_solve(self):
+        progress = []
+        output = solver.solve(solver_input(), on_progress=progress.append)
+        self.assertEqual(output["type"], "sudoku")
+        self.assertEqual(output["result"], "solved")
+        self.assertEqual(output["domains"]["1:3"], [7])
+        self.assertGreater(output["stats"]["guesses"], 0)
+        self.assertTrue(progress)
+
+    def test_stop(self):
+        output = solver.solve(solver_input(), on_progress=lambda progress: False)
+        self.assertEqual(output["result"], "timeout")
+
+        output = solver.solve(solver_input(), timeout_ms=0)
+        self.assertEqual(output["result"], "timeout")
+
+    def test_error(self):
+        puzzle = solver_input()
+        puzzle["type"] = "kakuro"
+        with self.assertRaises(solver.SolverError) as raised:
+            solver.solve(puzzle)
+        self.assertEqual(raised.exception.code, "unsupported")
+        self.assertEqual(raised.exception.field, "type")
+
+
+if __name__ == "__main__":
+    unittest.main()